@@ -1,7 +1,7 @@
 use common::packet_processor::Processor;
 use common::types::{
     ChatCommand, ChatEvent, ChatRequest, ChatResponse, Command, Event, Message, NodeCommand,
-    NodeEvent, ServerType,
+    NodeEvent, ServerInfo, ServerType,
 };
 use common::{FragmentAssembler, RoutingHandler};
 use crossbeam_channel::{Receiver, Sender};
@@ -20,6 +20,7 @@ pub struct ChatClient {
     // server
     pending_requests: VecDeque<ChatRequest>,
     communication_servers: HashSet<NodeId>,
+    servers_info: HashMap<NodeId, ServerInfo>, // what each server advertised about itself
     chats_history: HashMap<NodeId, Vec<Message>>,
 }
 
@@ -44,11 +45,18 @@ impl ChatClient {
             assembler: FragmentAssembler::default(),
             registered_clients: HashMap::new(),
             communication_servers: HashSet::new(),
+            servers_info: HashMap::new(),
             chats_history: HashMap::new(),
             pending_requests: VecDeque::new(),
         }
     }
 
+    /// Version and capabilities advertised by a server, if it answered a `ServerTypeQuery`
+    #[must_use]
+    pub fn get_server_info(&self, id: NodeId) -> Option<&ServerInfo> {
+        self.servers_info.get(&id)
+    }
+
     fn get_chats_history(&self) -> HashMap<NodeId, Vec<Message>> {
         self.chats_history.clone()
     }
@@ -241,6 +249,11 @@ impl Processor for ChatClient {
                 NodeCommand::Shutdown => {
                     return true;
                 }
+                NodeCommand::GetStats => self.report_stats(),
+                NodeCommand::GetNetworkView => self.report_network_view(),
+                NodeCommand::StartFlood => {
+                    let _ = self.routing_handler.start_flood();
+                }
             }
         }
         false
@@ -255,8 +268,17 @@ impl Processor for ChatClient {
             }));
         if let Ok(msg) = serde_json::from_slice::<ChatResponse>(&msg) {
             match msg {
-                ChatResponse::ServerType { server_type } => {
-                    if matches!(server_type, ServerType::ChatServer) {
+                ChatResponse::ServerType {
+                    server_type,
+                    protocol_version,
+                    capabilities,
+                } => {
+                    let is_chat_server = matches!(server_type, ServerType::ChatServer);
+                    self.servers_info.insert(
+                        from,
+                        ServerInfo::new(server_type, protocol_version, capabilities),
+                    );
+                    if is_chat_server {
                         self.communication_servers.insert(from);
                         self.try_send_pending_requests();
                     }
@@ -306,7 +328,7 @@ impl Processor for ChatClient {
 #[cfg(test)]
 mod chat_client_tests {
     use super::*;
-    use common::types::{ChatResponse, Message, ServerType};
+    use common::types::{Capability, ChatResponse, Message, PROTOCOL_VERSION, ServerType};
     use crossbeam::channel::unbounded;
 
     fn create_test_chat_client() -> ChatClient {
@@ -325,11 +347,17 @@ mod chat_client_tests {
 
         let response = ChatResponse::ServerType {
             server_type: ServerType::ChatServer,
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: vec![Capability::Rooms],
         };
         let serialized = serde_json::to_vec(&response).unwrap();
         client.handle_msg(serialized, 5, 100);
 
         assert!(client.communication_servers.contains(&5));
+        let info = client.get_server_info(5).unwrap();
+        assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+        assert!(info.supports(Capability::Rooms));
+        assert!(!info.supports(Capability::Uploads));
     }
 
     #[test]
@@ -1,9 +1,10 @@
 use crate::errors::ClientError;
 use common::{
     FragmentAssembler, Processor, RoutingHandler,
+    envelope::{Codec, Envelope},
     types::{
-        Command, Event, File, MediaFile, NodeCommand, NodeEvent, ServerType, TextFile, WebCommand,
-        WebEvent, WebRequest, WebResponse,
+        Command, Event, File, MediaFile, NodeCommand, NodeEvent, ServerInfo, ServerType, TextFile,
+        WebCommand, WebEvent, WebRequest, WebResponse,
     },
 };
 use crossbeam_channel::{Receiver, Sender};
@@ -16,6 +17,22 @@ use wg_internal::{
 
 type Cache = HashMap<TextFile, Vec<MediaFile>>;
 
+/// File request advertising the compact codecs this browser can decode
+fn file_query(uuid: Uuid) -> WebRequest {
+    WebRequest::FileQuery {
+        file_id: uuid.to_string(),
+        accepted_codecs: Codec::supported_ids(),
+    }
+}
+
+/// Media request advertising the compact codecs this browser can decode
+fn media_query(uuid: Uuid) -> WebRequest {
+    WebRequest::MediaQuery {
+        media_id: uuid.to_string(),
+        accepted_codecs: Codec::supported_ids(),
+    }
+}
+
 #[derive(Debug)]
 pub struct WebBrowser {
     id: NodeId,
@@ -25,6 +42,7 @@ pub struct WebBrowser {
     packet_recv: Receiver<Packet>,
     assembler: FragmentAssembler,
     text_servers: HashMap<NodeId, Vec<String>>, // id, file_list
+    servers_info: HashMap<NodeId, ServerInfo>,  // what each server advertised about itself
     cached_files: Cache,
     pending_request: Option<WebRequest>,
 }
@@ -49,6 +67,7 @@ impl WebBrowser {
             packet_recv,
             assembler: FragmentAssembler::default(),
             text_servers: HashMap::new(),
+            servers_info: HashMap::new(),
             cached_files: HashMap::new(),
             pending_request: None,
         }
@@ -58,6 +77,12 @@ impl WebBrowser {
         self.text_servers.keys().copied().collect()
     }
 
+    /// Version and capabilities advertised by a server, if it answered a `ServerTypeQuery`
+    #[must_use]
+    pub fn get_server_info(&self, id: NodeId) -> Option<&ServerInfo> {
+        self.servers_info.get(&id)
+    }
+
     fn get_list_files_by_id(&self, id: NodeId) -> Option<&Vec<String>> {
         self.text_servers.get(&id)
     }
@@ -127,14 +152,12 @@ impl WebBrowser {
         None
     }
 
-    fn manage_text_file(&mut self, file: TextFile, session_id: u64) {
+    fn manage_text_file(&mut self, file: TextFile) {
         for r in &file.get_refs() {
-            if let Ok(req) = serde_json::to_vec(&WebRequest::MediaQuery {
-                media_id: r.id.to_string(),
-            }) {
+            if let Ok(req) = serde_json::to_vec(&media_query(r.id)) {
                 let _ = self
                     .routing_handler
-                    .send_message(&req, r.get_location(), Some(session_id));
+                    .send_message(&req, r.get_location(), None);
             }
         }
         if file.get_refs().is_empty() {
@@ -198,15 +221,11 @@ impl WebBrowser {
                 file,
             });
         }
-        match self.forward_request(&WebRequest::FileQuery {
-            file_id: uuid.to_string(),
-        }) {
+        match self.forward_request(&file_query(uuid)) {
             Ok(()) => return false,
             Err(ClientError::NoLocationError) => {
                 self.broadcast();
-                self.pending_request = Some(WebRequest::FileQuery {
-                    file_id: uuid.to_string(),
-                });
+                self.pending_request = Some(file_query(uuid));
             }
             Err(e) => {
                 eprintln!("Error forwarding request: {e}");
@@ -232,15 +251,11 @@ impl WebBrowser {
                 file: file.clone(),
             });
         }
-        match self.forward_request(&WebRequest::FileQuery {
-            file_id: uuid.to_string(),
-        }) {
+        match self.forward_request(&file_query(uuid)) {
             Ok(()) => return false,
             Err(ClientError::NoLocationError) => {
                 self.broadcast();
-                self.pending_request = Some(WebRequest::FileQuery {
-                    file_id: uuid.to_string(),
-                });
+                self.pending_request = Some(file_query(uuid));
             }
             Err(e) => {
                 eprintln!("Error forwarding request: {e}");
@@ -267,9 +282,7 @@ impl WebBrowser {
                 });
             }
         }
-        if let Ok(req) = serde_json::to_vec(&WebRequest::MediaQuery {
-            media_id: media_id.to_string(),
-        }) {
+        if let Ok(req) = serde_json::to_vec(&media_query(media_id)) {
             let _ = self.routing_handler.send_message(&req, location, None);
         }
         false
@@ -314,13 +327,25 @@ impl Processor for WebBrowser {
             match cmd {
                 NodeCommand::AddSender(node_id, sender) => {
                     self.routing_handler.add_neighbor(*node_id, sender.clone());
-                    return false;
+                    false
                 }
                 NodeCommand::RemoveSender(node_id) => {
                     self.routing_handler.remove_neighbor(*node_id);
-                    return false;
+                    false
+                }
+                NodeCommand::Shutdown => true,
+                NodeCommand::GetStats => {
+                    self.report_stats();
+                    false
+                }
+                NodeCommand::GetNetworkView => {
+                    self.report_network_view();
+                    false
+                }
+                NodeCommand::StartFlood => {
+                    let _ = self.routing_handler.start_flood();
+                    false
                 }
-                NodeCommand::Shutdown => return true,
             }
         } else {
             false
@@ -328,16 +353,30 @@ impl Processor for WebBrowser {
     }
 
     fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, session_id: u64) {
+        self.handle_envelope(Envelope::new(Codec::Json, msg), from, session_id);
+    }
+
+    fn handle_envelope(&mut self, envelope: Envelope, from: NodeId, _session_id: u64) {
         let _ = self
             .controller_send
             .send(Box::new(NodeEvent::MessageReceived {
                 notification_from: self.id,
                 from,
             }));
-        if let Ok(msg) = serde_json::from_slice::<WebResponse>(&msg) {
+        let codec = envelope.codec;
+        if let Ok(msg) = WebResponse::decode(&envelope.payload, codec) {
             match msg {
-                WebResponse::ServerType { server_type } => {
-                    if matches!(server_type, ServerType::TextServer) {
+                WebResponse::ServerType {
+                    server_type,
+                    protocol_version,
+                    capabilities,
+                } => {
+                    let is_text_server = matches!(server_type, ServerType::TextServer);
+                    self.servers_info.insert(
+                        from,
+                        ServerInfo::new(server_type, protocol_version, capabilities),
+                    );
+                    if is_text_server {
                         self.text_servers.insert(from, vec![]);
                         let _ = self.forward_request(&WebRequest::TextFilesListQuery);
                     }
@@ -355,12 +394,12 @@ impl Processor for WebBrowser {
                     }
                 }
                 WebResponse::TextFile { file_data } => {
-                    if let Ok(file) = serde_json::from_slice::<TextFile>(&file_data) {
-                        self.manage_text_file(file, session_id);
+                    if let Ok(file) = codec.nested().decode::<TextFile>(&file_data) {
+                        self.manage_text_file(file);
                     }
                 }
                 WebResponse::MediaFile { media_data } => {
-                    if let Ok(mediafile) = serde_json::from_slice::<MediaFile>(&media_data) {
+                    if let Ok(mediafile) = codec.nested().decode::<MediaFile>(&media_data) {
                         // check if all media files are present, if yes send to controller
                         self.manage_media_file(mediafile);
                     }
@@ -386,7 +425,9 @@ impl Processor for WebBrowser {
 #[cfg(test)]
 mod web_browser_tests {
     use super::*;
-    use common::types::{MediaFile, MediaReference, ServerType, TextFile, WebResponse};
+    use common::types::{
+        Capability, MediaFile, MediaReference, PROTOCOL_VERSION, ServerType, TextFile, WebResponse,
+    };
     use crossbeam::channel::unbounded;
 
     fn create_test_web_browser() -> WebBrowser {
@@ -405,11 +446,41 @@ mod web_browser_tests {
 
         let response = WebResponse::ServerType {
             server_type: ServerType::TextServer,
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: vec![Capability::Compression],
         };
         let serialized = serde_json::to_vec(&response).unwrap();
         browser.handle_msg(serialized, 5, 100);
 
         assert!(browser.text_servers.contains_key(&5));
+        let info = browser.get_server_info(5).unwrap();
+        assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+        assert!(info.supports(Capability::Compression));
+    }
+
+    #[test]
+    /// Tests that `ServerType` responses from servers predating versioning, or advertising
+    /// capabilities unknown to this browser, are still understood
+    fn test_server_type_compatibility() {
+        let mut browser = create_test_web_browser();
+
+        let legacy = br#"{"response_type":"server_type!","server_type":"MediaServer"}"#.to_vec();
+        browser.handle_msg(legacy, 6, 100);
+        let info = browser.get_server_info(6).unwrap();
+        assert_eq!(info.server_type, ServerType::MediaServer);
+        assert_eq!(info.protocol_version, 0);
+        assert!(info.capabilities.is_empty());
+        assert!(!browser.text_servers.contains_key(&6));
+
+        let newer = br#"{"response_type":"server_type!","server_type":"TextServer","protocol_version":7,"capabilities":["search","time_travel"]}"#.to_vec();
+        browser.handle_msg(newer, 7, 101);
+        let info = browser.get_server_info(7).unwrap();
+        assert_eq!(info.protocol_version, 7);
+        assert_eq!(
+            info.capabilities,
+            vec![Capability::Search, Capability::Unknown]
+        );
+        assert!(browser.text_servers.contains_key(&7));
     }
 
     #[test]
@@ -502,4 +573,37 @@ mod web_browser_tests {
             assert!(should_not_continue);
         }
     }
+
+    #[test]
+    /// Tests that a media file sent with a compact codec is decoded
+    fn test_compact_media_file() {
+        let mut browser = create_test_web_browser();
+
+        let media_ref = MediaReference::new(6);
+        let text_file = TextFile::new(
+            "Article".to_string(),
+            "Content".to_string(),
+            vec![media_ref.clone()],
+        );
+        browser.cached_files.insert(text_file.clone(), vec![]);
+        let mut media_file = MediaFile::from_u8("Test Image".to_string(), &[9u8; 3000]);
+        media_file.id = media_ref.id;
+        let codec = Codec::BincodeDeflate;
+        let response = WebResponse::MediaFile {
+            media_data: codec.nested().encode(&media_file).unwrap(),
+        };
+        let json_len = serde_json::to_vec(&WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&media_file).unwrap(),
+        })
+        .unwrap()
+        .len();
+        let payload = response.encode(codec).unwrap();
+        assert!(payload.len() < json_len);
+        // the file is compressed once, along with the response
+        assert!(payload.len() <= codec.encode(&media_file).unwrap().len() + 8);
+
+        browser.handle_envelope(Envelope::new(codec, payload), 6, 103);
+
+        assert_eq!(browser.cached_files[&text_file], vec![media_file]);
+    }
 }
@@ -1,51 +1,563 @@
-use std::collections::hash_map::Entry::Vacant;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, btree_map::Entry};
+use std::time::{Duration, Instant};
 
 use wg_internal::{network::NodeId, packet::Fragment};
 
+/// Maximum number of payload bytes carried by a fragment
+pub const FRAGMENT_SIZE: usize = 128;
+/// Default maximum number of incomplete messages kept for a single sender
+pub const DEFAULT_MAX_SESSIONS_PER_SENDER: usize = 16;
+/// Default maximum number of payload bytes buffered across all incomplete messages
+pub const DEFAULT_MAX_BUFFERED_BYTES: usize = 64 * 1024 * 1024;
+/// Default time after which an incomplete message receiving no fragment is abandoned
+pub const DEFAULT_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(60);
 
-#[derive(Debug, Default)]
+/// Fragments received so far for a message
+#[derive(Debug)]
+struct PartialMessage {
+    total_n_fragments: u64,
+    fragments: BTreeMap<u64, Fragment>, // fragment_index -> fragment
+    bytes: usize,
+    started_at: Instant,
+    last_update: Instant,
+    last_report: Option<Instant>,
+    reported_fragments: usize,
+}
+
+impl PartialMessage {
+    fn progress(&self, session_id: u64, sender: NodeId) -> ReassemblyProgress {
+        ReassemblyProgress {
+            session_id,
+            sender,
+            received_fragments: self.fragments.len() as u64,
+            total_fragments: self.total_n_fragments,
+            received_bytes: self.bytes,
+            started_at: self.started_at,
+        }
+    }
+}
+
+/// Progress of an incomplete message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReassemblyProgress {
+    pub session_id: u64,
+    pub sender: NodeId,
+    pub received_fragments: u64,
+    pub total_fragments: u64,
+    pub received_bytes: usize,
+    pub started_at: Instant,
+}
+
+/// An incomplete message dropped by the assembler, because it expired or to make room for others
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbandonedMessage {
+    pub session_id: u64,
+    pub sender: NodeId,
+    pub received_fragments: u64,
+    pub total_fragments: u64,
+}
+
+#[derive(Debug)]
 pub struct FragmentAssembler {
-    pub fragments: HashMap<(u64, NodeId), Vec<Fragment>>, // session_id -> data buffer
-    pub expected_fragments: HashMap<(u64, NodeId), u64>, // session_id -> total_fragments
-    pub received_fragments: HashMap<(u64, NodeId), Vec<bool>>, // session_id -> received status
+    sessions: HashMap<(u64, NodeId), PartialMessage>, // (session_id, sender) -> received fragments
+    buffered_bytes: usize,
+    abandoned: Vec<AbandonedMessage>,
+    max_sessions_per_sender: usize,
+    max_buffered_bytes: usize,
+    idle_timeout: Duration,
+}
+
+impl Default for FragmentAssembler {
+    fn default() -> Self {
+        Self {
+            sessions: HashMap::new(),
+            buffered_bytes: 0,
+            abandoned: Vec::new(),
+            max_sessions_per_sender: DEFAULT_MAX_SESSIONS_PER_SENDER,
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+            idle_timeout: DEFAULT_REASSEMBLY_TIMEOUT,
+        }
+    }
 }
 
 impl FragmentAssembler {
+    /// Sets how many incomplete messages a sender may have, how many bytes may be buffered
+    /// overall and after how long without new fragments a message is abandoned.
+    pub fn set_limits(
+        &mut self,
+        max_sessions_per_sender: usize,
+        max_buffered_bytes: usize,
+        idle_timeout: Duration,
+    ) {
+        self.max_sessions_per_sender = max_sessions_per_sender.max(1);
+        self.max_buffered_bytes = max_buffered_bytes;
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Stores a fragment of the message sent by `sender` in `session_id`.
+    /// Fragments may arrive in any order, duplicates are ignored, as are fragments which
+    /// are inconsistent with the ones already received (index out of range, different total
+    /// or invalid length). Once every fragment has arrived the original bytes are returned.
+    /// The oldest incomplete messages are abandoned when a limit would be exceeded.
     pub fn add_fragment(&mut self, fragment: Fragment, session_id: u64, sender: NodeId) -> Option<Vec<u8>> {
-        let communication_id = ( session_id, sender );
-        #[allow(clippy::cast_possible_truncation)]
-        let index = fragment.fragment_index as usize;
+        let communication_id = (session_id, sender);
+        let length = usize::from(fragment.length);
+        let now = Instant::now();
 
-        if let Vacant(entry) = self.fragments.entry(communication_id) {
-            self.expected_fragments.insert(communication_id, fragment.total_n_fragments);
-            self.received_fragments.insert(communication_id, vec![false; index]);
-            entry.insert(vec![fragment]);
+        if fragment.fragment_index >= fragment.total_n_fragments || length > FRAGMENT_SIZE {
+            return None;
         }
 
-        {
-            let received = self.received_fragments.get_mut(&communication_id)?;
-            received[index] = true;
+        if let Some(message) = self.sessions.get_mut(&communication_id) {
+            if fragment.total_n_fragments != message.total_n_fragments {
+                return None;
+            }
+            // a duplicate still shows that the sender is alive
+            message.last_update = now;
+            if message.fragments.contains_key(&fragment.fragment_index) {
+                return None;
+            }
+        } else {
+            while self.sessions.keys().filter(|(_, s)| *s == sender).count()
+                >= self.max_sessions_per_sender
+            {
+                let oldest = self.oldest_session(|(_, s)| *s == sender)?;
+                self.abandon(oldest);
+            }
         }
 
-        let expected = self.expected_fragments.get(&communication_id)?;
-        let received = self.received_fragments.get(&communication_id)?;
-        let fragments = self.fragments.get(&communication_id)?;
+        while self.buffered_bytes + length > self.max_buffered_bytes {
+            let Some(oldest) = self.oldest_session(|id| *id != communication_id) else {
+                // the message alone does not fit
+                if self.sessions.contains_key(&communication_id) {
+                    self.abandon(communication_id);
+                } else {
+                    self.abandoned.push(AbandonedMessage {
+                        session_id,
+                        sender,
+                        received_fragments: 0,
+                        total_fragments: fragment.total_n_fragments,
+                    });
+                }
+                return None;
+            };
+            self.abandon(oldest);
+        }
+
+        let message = self
+            .sessions
+            .entry(communication_id)
+            .or_insert_with(|| PartialMessage {
+                total_n_fragments: fragment.total_n_fragments,
+                fragments: BTreeMap::new(),
+                bytes: 0,
+                started_at: now,
+                last_update: now,
+                last_report: None,
+                reported_fragments: 0,
+            });
+        if let Entry::Vacant(entry) = message.fragments.entry(fragment.fragment_index) {
+            let _ = entry.insert(fragment);
+            message.bytes += length;
+            self.buffered_bytes += length;
+        }
 
         // check if all fragments has been received
-        if fragments.len() as u64 == *expected && received.iter().all(|f| *f){
-            let fragments = self.fragments.get_mut(&communication_id)?;
-            fragments.sort_by(|t, n| t.fragment_index.cmp(&n.fragment_index));
-            let mut data = vec![];
-            for f in fragments {
-                data.copy_from_slice(&f.data);
+        if message.fragments.len() as u64 == message.total_n_fragments {
+            let message = self.sessions.remove(&communication_id)?;
+            self.buffered_bytes -= message.bytes;
+            let mut data = Vec::with_capacity(message.bytes);
+            for f in message.fragments.values() {
+                data.extend_from_slice(&f.data[..usize::from(f.length)]);
             }
-            let _ = self.fragments.remove(&communication_id);
-            let _ = self.received_fragments.remove(&communication_id);
-            let _ = self.expected_fragments.remove(&communication_id);
             return Some(data);
         }
         None
     }
+
+    /// Abandons the incomplete messages which received no fragment for longer than the idle timeout
+    pub fn expire(&mut self, now: Instant) {
+        let idle = self
+            .sessions
+            .iter()
+            .filter(|(_, m)| now.saturating_duration_since(m.last_update) > self.idle_timeout)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in idle {
+            self.abandon(id);
+        }
+    }
+
+    /// Abandons every incomplete message, e.g. because the node is shutting down
+    pub fn abandon_all(&mut self) {
+        let ids = self.sessions.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            self.abandon(id);
+        }
+    }
+
+    /// Returns the progress of the message sent by `sender` in `session_id`, if it is incomplete
+    #[must_use]
+    pub fn get_progress(&self, session_id: u64, sender: NodeId) -> Option<ReassemblyProgress> {
+        self.sessions
+            .get(&(session_id, sender))
+            .map(|m| m.progress(session_id, sender))
+    }
+
+    /// Returns the progress of the incomplete messages which received new fragments since
+    /// they were last reported, reporting each message at most once every `interval`
+    pub fn progress_updates(
+        &mut self,
+        now: Instant,
+        interval: Duration,
+    ) -> Vec<ReassemblyProgress> {
+        let mut updates = Vec::new();
+        for (&(session_id, sender), message) in &mut self.sessions {
+            let due = message
+                .last_report
+                .is_none_or(|last| now.saturating_duration_since(last) >= interval);
+            if due && message.fragments.len() > message.reported_fragments {
+                message.last_report = Some(now);
+                message.reported_fragments = message.fragments.len();
+                updates.push(message.progress(session_id, sender));
+            }
+        }
+        updates.sort_by_key(|p| (p.started_at, p.session_id, p.sender));
+        updates
+    }
+
+    /// Returns the messages abandoned since the last call
+    pub fn take_abandoned(&mut self) -> Vec<AbandonedMessage> {
+        self.abandoned.drain(..).collect()
+    }
+
+    /// Number of payload bytes currently buffered for incomplete messages
+    #[must_use]
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    fn oldest_session(&self, filter: impl Fn(&(u64, NodeId)) -> bool) -> Option<(u64, NodeId)> {
+        self.sessions
+            .iter()
+            .filter(|(id, _)| filter(id))
+            .min_by_key(|(id, m)| (m.started_at, **id))
+            .map(|(id, _)| *id)
+    }
+
+    fn abandon(&mut self, communication_id: (u64, NodeId)) {
+        if let Some(message) = self.sessions.remove(&communication_id) {
+            self.buffered_bytes -= message.bytes;
+            self.abandoned.push(AbandonedMessage {
+                session_id: communication_id.0,
+                sender: communication_id.1,
+                received_fragments: message.fragments.len() as u64,
+                total_fragments: message.total_n_fragments,
+            });
+        }
+    }
 }
 
+#[cfg(test)]
+mod assembler_tests {
+    use super::*;
+
+    fn fragments_of(message: &[u8]) -> Vec<Fragment> {
+        let chunks = message.chunks(FRAGMENT_SIZE).collect::<Vec<_>>();
+        let total = chunks.len() as u64;
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut data = [0u8; FRAGMENT_SIZE];
+                data[..chunk.len()].copy_from_slice(chunk);
+                #[allow(clippy::cast_possible_truncation)]
+                Fragment {
+                    fragment_index: i as u64,
+                    total_n_fragments: total,
+                    length: chunk.len() as u8,
+                    data,
+                }
+            })
+            .collect()
+    }
+
+    fn message(len: usize) -> Vec<u8> {
+        #[allow(clippy::cast_possible_truncation)]
+        (0..len).map(|i| (i % 251) as u8 + 1).collect()
+    }
+
+    #[test]
+    /// Tests reassembling a single fragment message, stripping the padding
+    fn test_single_fragment() {
+        let mut assembler = FragmentAssembler::default();
+        let msg = b"Hello world".to_vec();
+
+        let fragment = fragments_of(&msg).remove(0);
+        assert_eq!(assembler.add_fragment(fragment, 1, 2), Some(msg));
+        assert!(assembler.sessions.is_empty());
+    }
+
+    #[test]
+    /// Tests reassembling fragments received in order
+    fn test_in_order() {
+        let mut assembler = FragmentAssembler::default();
+        let msg = message(1000);
+        let fragments = fragments_of(&msg);
+        let last = fragments.len() - 1;
+
+        for (i, fragment) in fragments.into_iter().enumerate() {
+            let result = assembler.add_fragment(fragment, 1, 2);
+            if i == last {
+                assert_eq!(result, Some(msg.clone()));
+            } else {
+                assert_eq!(result, None);
+            }
+        }
+    }
+
+    #[test]
+    /// Tests reassembling fragments received in reverse and shuffled order
+    fn test_out_of_order() {
+        let msg = message(128 * 5 + 17);
+
+        let mut assembler = FragmentAssembler::default();
+        let mut result = None;
+        for fragment in fragments_of(&msg).into_iter().rev() {
+            result = assembler.add_fragment(fragment, 1, 2);
+        }
+        assert_eq!(result, Some(msg.clone()));
+
+        let mut fragments = fragments_of(&msg);
+        fragments.swap(0, 3);
+        fragments.swap(1, 5);
+        let mut result = None;
+        for fragment in fragments {
+            result = assembler.add_fragment(fragment, 1, 2);
+        }
+        assert_eq!(result, Some(msg));
+    }
+
+    #[test]
+    /// Tests that duplicated fragments neither complete a message early nor corrupt it
+    fn test_duplicates_ignored() {
+        let mut assembler = FragmentAssembler::default();
+        let msg = message(128 * 3);
+        let fragments = fragments_of(&msg);
+
+        assert_eq!(assembler.add_fragment(fragments[0].clone(), 1, 2), None);
+        assert_eq!(assembler.add_fragment(fragments[0].clone(), 1, 2), None);
+        assert_eq!(assembler.add_fragment(fragments[2].clone(), 1, 2), None);
+        assert_eq!(assembler.add_fragment(fragments[2].clone(), 1, 2), None);
+        assert_eq!(
+            assembler.add_fragment(fragments[1].clone(), 1, 2),
+            Some(msg)
+        );
+    }
+
+    #[test]
+    /// Tests that messages are kept apart by session and by sender
+    fn test_interleaved_sessions() {
+        let mut assembler = FragmentAssembler::default();
+        let first = message(300);
+        let second = vec![9u8; 200];
+        let third = vec![7u8; 130];
+
+        let mut a = fragments_of(&first).into_iter();
+        let mut b = fragments_of(&second).into_iter();
+        let mut c = fragments_of(&third).into_iter();
+
+        assert_eq!(assembler.add_fragment(a.next().unwrap(), 1, 2), None);
+        assert_eq!(assembler.add_fragment(b.next().unwrap(), 2, 2), None);
+        assert_eq!(assembler.add_fragment(c.next().unwrap(), 1, 3), None);
+        assert_eq!(assembler.add_fragment(a.next().unwrap(), 1, 2), None);
+        assert_eq!(assembler.add_fragment(c.next().unwrap(), 1, 3), Some(third));
+        assert_eq!(
+            assembler.add_fragment(b.next().unwrap(), 2, 2),
+            Some(second)
+        );
+        assert_eq!(assembler.add_fragment(a.next().unwrap(), 1, 2), Some(first));
+    }
+
+    #[test]
+    /// Tests that trailing zeros belonging to the message are kept
+    fn test_exact_length_with_trailing_zeros() {
+        let mut assembler = FragmentAssembler::default();
+        let mut msg = message(140);
+        msg.extend_from_slice(&[0, 0, 0]);
+
+        let mut result = None;
+        for fragment in fragments_of(&msg) {
+            result = assembler.add_fragment(fragment, 1, 2);
+        }
+        assert_eq!(result, Some(msg));
+    }
+
+    #[test]
+    /// Tests that inconsistent fragments are rejected
+    fn test_invalid_fragments() {
+        let mut assembler = FragmentAssembler::default();
+        let msg = message(256);
+        let mut fragments = fragments_of(&msg);
+
+        let mut out_of_range = fragments[0].clone();
+        out_of_range.fragment_index = 2;
+        assert_eq!(assembler.add_fragment(out_of_range, 1, 2), None);
+
+        let mut too_long = fragments[0].clone();
+        too_long.length = 200;
+        assert_eq!(assembler.add_fragment(too_long, 1, 2), None);
+        assert!(assembler.sessions.is_empty());
+
+        assert_eq!(assembler.add_fragment(fragments.remove(0), 1, 2), None);
+        let mut other_total = fragments[0].clone();
+        other_total.total_n_fragments = 3;
+        assert_eq!(assembler.add_fragment(other_total, 1, 2), None);
+
+        assert_eq!(assembler.add_fragment(fragments.remove(0), 1, 2), Some(msg));
+    }
+
+    #[test]
+    /// Tests that a sender exceeding its session limit loses its oldest incomplete message
+    fn test_sessions_per_sender_limit() {
+        let mut assembler = FragmentAssembler::default();
+        assembler.set_limits(2, DEFAULT_MAX_BUFFERED_BYTES, DEFAULT_REASSEMBLY_TIMEOUT);
+        let msg = message(256);
+
+        for session_id in 1..=3 {
+            assert_eq!(
+                assembler.add_fragment(fragments_of(&msg).remove(0), session_id, 2),
+                None
+            );
+        }
+        // another sender is not affected
+        assert_eq!(
+            assembler.add_fragment(fragments_of(&msg).remove(0), 1, 3),
+            None
+        );
+
+        assert_eq!(
+            assembler.take_abandoned(),
+            vec![AbandonedMessage {
+                session_id: 1,
+                sender: 2,
+                received_fragments: 1,
+                total_fragments: 2,
+            }]
+        );
+        assert_eq!(
+            assembler.add_fragment(fragments_of(&msg).remove(1), 3, 2),
+            Some(msg)
+        );
+        assert!(assembler.take_abandoned().is_empty());
+    }
+
+    #[test]
+    /// Tests that the oldest messages are abandoned to stay below the buffered bytes limit
+    fn test_buffered_bytes_limit() {
+        let mut assembler = FragmentAssembler::default();
+        assembler.set_limits(
+            DEFAULT_MAX_SESSIONS_PER_SENDER,
+            300,
+            DEFAULT_REASSEMBLY_TIMEOUT,
+        );
+        let msg = message(128 * 4);
+
+        let mut first = fragments_of(&msg).into_iter();
+        assert_eq!(assembler.add_fragment(first.next().unwrap(), 1, 2), None);
+        assert_eq!(assembler.add_fragment(first.next().unwrap(), 1, 2), None);
+        assert_eq!(assembler.buffered_bytes(), 256);
+
+        assert_eq!(
+            assembler.add_fragment(fragments_of(&msg).remove(0), 2, 3),
+            None
+        );
+        assert_eq!(assembler.buffered_bytes(), 128);
+        let abandoned = assembler.take_abandoned();
+        assert_eq!(abandoned.len(), 1);
+        assert_eq!((abandoned[0].session_id, abandoned[0].sender), (1, 2));
+        assert_eq!(abandoned[0].received_fragments, 2);
+
+        // a message which can never fit is dropped as well
+        let mut second = fragments_of(&msg).into_iter().skip(1);
+        assert_eq!(assembler.add_fragment(second.next().unwrap(), 2, 3), None);
+        assert_eq!(assembler.add_fragment(second.next().unwrap(), 2, 3), None);
+        assert_eq!(assembler.buffered_bytes(), 0);
+        assert_eq!(assembler.take_abandoned()[0].session_id, 2);
+    }
+
+    #[test]
+    /// Tests that idle incomplete messages are abandoned
+    fn test_idle_messages_expire() {
+        let mut assembler = FragmentAssembler::default();
+        let msg = message(256);
+
+        assert_eq!(
+            assembler.add_fragment(fragments_of(&msg).remove(0), 1, 2),
+            None
+        );
+        assembler.expire(Instant::now());
+        assert!(assembler.take_abandoned().is_empty());
+
+        assembler.expire(Instant::now() + DEFAULT_REASSEMBLY_TIMEOUT * 2);
+        assert_eq!(assembler.take_abandoned().len(), 1);
+        assert_eq!(assembler.buffered_bytes(), 0);
+        assert!(assembler.sessions.is_empty());
+    }
+
+    #[test]
+    /// Tests the progress of an incomplete message and its periodic updates
+    fn test_progress_updates() {
+        let mut assembler = FragmentAssembler::default();
+        let interval = Duration::from_millis(500);
+        let msg = message(128 * 3 + 10);
+        let mut fragments = fragments_of(&msg).into_iter();
+
+        assert_eq!(
+            assembler.add_fragment(fragments.next().unwrap(), 1, 2),
+            None
+        );
+        let progress = assembler.get_progress(1, 2).unwrap();
+        assert_eq!(progress.received_fragments, 1);
+        assert_eq!(progress.total_fragments, 4);
+        assert_eq!(progress.received_bytes, 128);
+
+        let now = Instant::now();
+        assert_eq!(assembler.progress_updates(now, interval), vec![progress]);
+        // nothing new to report
+        assert!(
+            assembler
+                .progress_updates(now + interval, interval)
+                .is_empty()
+        );
+
+        assert_eq!(
+            assembler.add_fragment(fragments.next().unwrap(), 1, 2),
+            None
+        );
+        // reported at most once per interval
+        assert!(
+            assembler
+                .progress_updates(now + interval / 2, interval)
+                .is_empty()
+        );
+        let updates = assembler.progress_updates(now + interval, interval);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].received_fragments, 2);
+
+        assert_eq!(
+            assembler.add_fragment(fragments.next().unwrap(), 1, 2),
+            None
+        );
+        assert_eq!(
+            assembler.add_fragment(fragments.next().unwrap(), 1, 2),
+            Some(msg)
+        );
+        assert!(assembler.get_progress(1, 2).is_none());
+        assert!(
+            assembler
+                .progress_updates(now + interval * 4, interval)
+                .is_empty()
+        );
+    }
+}
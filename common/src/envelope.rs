@@ -0,0 +1,336 @@
+use std::fmt::Display;
+use std::io::{Read, Write};
+
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Version of the envelope layout written by this node
+pub const ENVELOPE_VERSION: u8 = 1;
+/// Size of the envelope header: version, codec, payload length and checksum
+pub const HEADER_LEN: usize = 10;
+/// Decompressed payloads larger than this are rejected
+pub const MAX_DECODED_LEN: usize = 64 * 1024 * 1024;
+
+/// Encoding of the payload carried by an envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Codec {
+    /// `serde_json`, understood by every node
+    Json,
+    /// `bincode`, byte vectors are sent as they are instead of as JSON arrays of numbers
+    Bincode,
+    /// `bincode` compressed with deflate
+    BincodeDeflate,
+}
+
+/// Codecs this node can decode, most compact first
+pub const SUPPORTED_CODECS: [Codec; 3] = [Codec::BincodeDeflate, Codec::Bincode, Codec::Json];
+
+impl Codec {
+    #[must_use]
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Json => 0,
+            Self::Bincode => 1,
+            Self::BincodeDeflate => 2,
+        }
+    }
+
+    /// Ids of the codecs this node can decode, to be advertised to peers
+    #[must_use]
+    pub fn supported_ids() -> Vec<u8> {
+        SUPPORTED_CODECS.iter().map(|c| c.id()).collect()
+    }
+
+    /// Picks the most compact codec among the ones a peer accepts (given by id),
+    /// falling back to JSON for peers which do not advertise any
+    #[must_use]
+    pub fn negotiate(accepted: &[u8]) -> Self {
+        SUPPORTED_CODECS
+            .into_iter()
+            .find(|c| accepted.contains(&c.id()))
+            .unwrap_or(Self::Json)
+    }
+
+    /// Codec for values serialized into a byte field of a message encoded with this one
+    /// (e.g. the file in a `WebResponse`): JSON stays JSON for the peers which predate
+    /// negotiation, binary codecs use plain `bincode` so the message is compressed only once
+    #[must_use]
+    pub fn nested(self) -> Self {
+        match self {
+            Self::Json => Self::Json,
+            Self::Bincode | Self::BincodeDeflate => Self::Bincode,
+        }
+    }
+
+    /// Serializes a value with this codec
+    /// # Errors
+    /// Returns an error if the value can't be serialized or compressed.
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, EnvelopeError> {
+        let to_error = |e: &dyn Display| EnvelopeError::Encoding(e.to_string());
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| to_error(&e)),
+            Self::Bincode => bincode::serialize(value).map_err(|e| to_error(&e)),
+            Self::BincodeDeflate => {
+                let bytes = bincode::serialize(value).map_err(|e| to_error(&e))?;
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes).map_err(|e| to_error(&e))?;
+                encoder.finish().map_err(|e| to_error(&e))
+            }
+        }
+    }
+
+    /// Deserializes a value encoded with this codec
+    /// # Errors
+    /// Returns an error if the bytes can't be decompressed or deserialized.
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, EnvelopeError> {
+        let to_error = |e: &dyn Display| EnvelopeError::Encoding(e.to_string());
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(|e| to_error(&e)),
+            Self::Bincode => bincode::deserialize(bytes).map_err(|e| to_error(&e)),
+            Self::BincodeDeflate => {
+                let mut decoded = Vec::new();
+                DeflateDecoder::new(bytes)
+                    .take(MAX_DECODED_LEN as u64 + 1)
+                    .read_to_end(&mut decoded)
+                    .map_err(|e| to_error(&e))?;
+                if decoded.len() > MAX_DECODED_LEN {
+                    return Err(EnvelopeError::Encoding(format!(
+                        "Decompressed payload exceeds {MAX_DECODED_LEN} bytes"
+                    )));
+                }
+                bincode::deserialize(&decoded).map_err(|e| to_error(&e))
+            }
+        }
+    }
+}
+
+impl TryFrom<u8> for Codec {
+    type Error = EnvelopeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Json),
+            1 => Ok(Self::Bincode),
+            2 => Ok(Self::BincodeDeflate),
+            other => Err(EnvelopeError::UnknownCodec(other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvelopeError {
+    TooShort(usize),
+    UnsupportedVersion(u8),
+    UnknownCodec(u8),
+    LengthMismatch { declared: usize, actual: usize },
+    ChecksumMismatch,
+    Encoding(String),
+}
+
+impl Display for EnvelopeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(len) => write!(
+                f,
+                "Message of {len} bytes is shorter than the envelope header"
+            ),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported envelope version {v}"),
+            Self::UnknownCodec(id) => write!(f, "Unknown codec {id}"),
+            Self::LengthMismatch { declared, actual } => {
+                write!(f, "Payload length is {actual} bytes, {declared} declared")
+            }
+            Self::ChecksumMismatch => write!(f, "Checksum mismatch"),
+            Self::Encoding(msg) => write!(f, "Encoding error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+/// A message as sent over the network: its payload and how the payload is encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub version: u8,
+    pub codec: Codec,
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    #[must_use]
+    pub fn new(codec: Codec, payload: Vec<u8>) -> Self {
+        Self {
+            version: ENVELOPE_VERSION,
+            codec,
+            payload,
+        }
+    }
+
+    /// Serializes the envelope as `version | codec | length (u32 BE) | crc32 (u32 BE) | payload`.
+    /// The checksum covers the first three header fields and the payload.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let length = u32::try_from(self.payload.len()).unwrap_or(u32::MAX);
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        bytes.push(self.version);
+        bytes.push(self.codec.id());
+        bytes.extend_from_slice(&length.to_be_bytes());
+        let checksum = crc32(&[&bytes[..6], &self.payload]);
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Parses and verifies an envelope
+    /// # Errors
+    /// Returns an error if the header is truncated or unknown, or if the payload
+    /// does not match the declared length or checksum.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EnvelopeError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(EnvelopeError::TooShort(bytes.len()));
+        }
+        let (header, payload) = bytes.split_at(HEADER_LEN);
+
+        let version = header[0];
+        if version != ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+        let codec = Codec::try_from(header[1])?;
+        let declared = u32::from_be_bytes([header[2], header[3], header[4], header[5]]) as usize;
+        if declared != payload.len() {
+            return Err(EnvelopeError::LengthMismatch {
+                declared,
+                actual: payload.len(),
+            });
+        }
+        let checksum = u32::from_be_bytes([header[6], header[7], header[8], header[9]]);
+        if checksum != crc32(&[&header[..6], payload]) {
+            return Err(EnvelopeError::ChecksumMismatch);
+        }
+
+        Ok(Self {
+            version,
+            codec,
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of the concatenation of `chunks`
+fn crc32(chunks: &[&[u8]]) -> u32 {
+    let mut crc = u32::MAX;
+    for byte in chunks.iter().flat_map(|c| c.iter()) {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod envelope_tests {
+    use super::*;
+
+    #[test]
+    /// Tests the checksum against the standard check value
+    fn test_crc32() {
+        assert_eq!(crc32(&[b"123456789"]), 0xCBF4_3926);
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xCBF4_3926);
+    }
+
+    #[test]
+    /// Tests that an envelope survives a round trip
+    fn test_round_trip() {
+        let envelope = Envelope::new(Codec::Json, br#"{"ServerTypeQuery":null}"#.to_vec());
+        let bytes = envelope.to_bytes();
+        assert_eq!(bytes.len(), HEADER_LEN + envelope.payload.len());
+        assert_eq!(Envelope::from_bytes(&bytes), Ok(envelope));
+
+        let empty = Envelope::new(Codec::Json, vec![]);
+        assert_eq!(Envelope::from_bytes(&empty.to_bytes()), Ok(empty));
+    }
+
+    #[test]
+    /// Tests that corrupted or malformed envelopes are rejected
+    fn test_corruption_detected() {
+        let bytes = Envelope::new(Codec::Json, b"hello world".to_vec()).to_bytes();
+
+        let mut flipped = bytes.clone();
+        flipped[HEADER_LEN + 3] ^= 0x10;
+        assert_eq!(
+            Envelope::from_bytes(&flipped),
+            Err(EnvelopeError::ChecksumMismatch)
+        );
+
+        let mut truncated = bytes.clone();
+        truncated.pop();
+        assert!(matches!(
+            Envelope::from_bytes(&truncated),
+            Err(EnvelopeError::LengthMismatch {
+                declared: 11,
+                actual: 10
+            })
+        ));
+
+        let mut version = bytes.clone();
+        version[0] = 9;
+        assert_eq!(
+            Envelope::from_bytes(&version),
+            Err(EnvelopeError::UnsupportedVersion(9))
+        );
+
+        let mut codec = bytes.clone();
+        codec[1] = 200;
+        assert_eq!(
+            Envelope::from_bytes(&codec),
+            Err(EnvelopeError::UnknownCodec(200))
+        );
+
+        assert_eq!(
+            Envelope::from_bytes(&bytes[..4]),
+            Err(EnvelopeError::TooShort(4))
+        );
+    }
+
+    #[test]
+    /// Tests that every codec round trips and that the compact ones are smaller than JSON
+    fn test_codecs() {
+        let value = (
+            String::from("media"),
+            vec![vec![7u8; 1000], (0..=255).collect::<Vec<u8>>()],
+        );
+
+        let mut sizes = Vec::new();
+        for codec in SUPPORTED_CODECS {
+            let bytes = codec.encode(&value).unwrap();
+            assert_eq!(
+                codec.decode::<(String, Vec<Vec<u8>>)>(&bytes).unwrap(),
+                value
+            );
+            sizes.push(bytes.len());
+        }
+        // deflate < bincode < json
+        assert!(sizes[0] < sizes[1] && sizes[1] < sizes[2]);
+
+        assert!(
+            Codec::Bincode
+                .decode::<(String, Vec<Vec<u8>>)>(b"garbage")
+                .is_err()
+        );
+    }
+
+    #[test]
+    /// Tests the codec choice against the ones a peer accepts
+    fn test_negotiate() {
+        assert_eq!(Codec::negotiate(&[]), Codec::Json);
+        assert_eq!(Codec::negotiate(&[1]), Codec::Bincode);
+        assert_eq!(Codec::negotiate(&[0, 1, 2]), Codec::BincodeDeflate);
+        // ids unknown to this node are skipped
+        assert_eq!(Codec::negotiate(&[42]), Codec::Json);
+        assert_eq!(
+            Codec::negotiate(&Codec::supported_ids()),
+            SUPPORTED_CODECS[0]
+        );
+    }
+}
@@ -5,6 +5,8 @@ pub mod assembler;
 pub mod routing_handler;
 pub mod packet_processor;
 pub mod file_conversion;
+pub mod envelope;
+pub mod network_export;
 
 pub use routing_handler::RoutingHandler;
 pub use assembler::FragmentAssembler;
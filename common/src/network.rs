@@ -1,12 +1,23 @@
 use crossbeam_channel::SendError;
 use wg_internal::network::NodeId;
 use wg_internal::packet::NodeType;
-use std::{collections::{HashMap, HashSet, VecDeque}, fmt::Display};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque},
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// How many extra hops a node with an estimated drop rate of `1 - 1/e` is worth
+const RELIABILITY_WEIGHT: f64 = 10.0;
+/// Drop rates are clamped to this value so that the cost of a node stays finite
+const MAX_DROP_RATE: f64 = 0.99;
 
 #[derive(Debug)]
 pub enum NetworkError {
     TopologyError,
     PathNotFound(u8),
+    PathThroughNonDrone(u8),
     NodeNotFound(u8),
     NodeIsNotANeighbor(u8),
     SendError(String),
@@ -20,6 +31,9 @@ impl Display for NetworkError {
         match self {
             Self::TopologyError => write!(f, "Topology error"),
             Self::PathNotFound(id) => write!(f, "Path not found for node {id}"),
+            Self::PathThroughNonDrone(id) => {
+                write!(f, "Node {id} is only reachable through clients or servers")
+            }
             Self::NodeNotFound(id) => write!(f, "Node {id} not found"),
             Self::NodeIsNotANeighbor(id) => write!(f, "Node {id} is not a neighbor"),
             Self::SendError(msg) => write!(f, "Send error: {msg}"),
@@ -44,14 +58,20 @@ impl<T: Send + std::fmt::Debug> From<SendError<T>> for NetworkError {
 pub struct Node {
     pub id: NodeId,
     kind: NodeType,
-    adjacents: Vec<NodeId>
+    adjacents: Vec<NodeId>,
+    last_seen: Instant,
 }
 
 
 impl Node {
     #[must_use]
     pub fn new(id: NodeId, kind: NodeType, adjacents: Vec<NodeId>) -> Self {
-        Self { id, kind, adjacents }
+        Self {
+            id,
+            kind,
+            adjacents,
+            last_seen: Instant::now(),
+        }
     }
 
     pub fn get_id(&self) -> NodeId {
@@ -62,15 +82,26 @@ impl Node {
         self.kind
     }
 
+    /// Last time the node appeared in a path trace or was updated
+    #[must_use]
+    pub fn get_last_seen(&self) -> Instant {
+        self.last_seen
+    }
+
     #[must_use]
     pub fn get_adjacents(&self) -> &Vec<NodeId> {
         &self.adjacents
     }
 
+    /// Adds `adj` to this node only: the edges of a node inside a `Network`
+    /// are changed with `Network::add_edge`, which updates both endpoints
     pub fn add_adjacent(&mut self, adj: NodeId) {
-        self.adjacents.push(adj);
+        if !self.adjacents.contains(&adj) {
+            self.adjacents.push(adj);
+        }
     }
 
+    /// Removes `adj` from this node only, see `add_adjacent` and `Network::remove_edge`
     pub fn remove_adjacent(&mut self, adj: NodeId) {
         if let Some(index_to_remove) = self.adjacents.iter().position(|i| *i == adj) {
             let _ = self.adjacents.remove(index_to_remove);
@@ -84,80 +115,347 @@ impl std::fmt::Debug for Node{
     }
 }
 
+/// Entry of the Dijkstra priority queue, ordered so that the cheapest node is popped first
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PathCost {
+    cost: f64,
+    node: NodeId,
+}
+
+impl Eq for PathCost {}
+
+impl Ord for PathCost {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for PathCost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Key of an undirected edge in `Network::edges_seen`
+fn edge_key(a: NodeId, b: NodeId) -> (NodeId, NodeId) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// Undirected graph of the known nodes, indexed by id.
+/// Edges are symmetric: whenever both endpoints are known, each one lists the other
+/// among its adjacents. A node may also list an id which is not known yet (e.g. a
+/// neighbor whose type has not been learned), the edge is completed when that node is added.
 #[derive(Debug, Clone, Default)]
 pub struct Network {
-    pub nodes: Vec<Node>
+    nodes: BTreeMap<NodeId, Node>,
+    // node path searches start from, the first node added unless set explicitly
+    source: Option<NodeId>,
+    // last time each edge was confirmed
+    edges_seen: HashMap<(NodeId, NodeId), Instant>,
 }
 
 impl Network {
     #[must_use]
     pub(crate) fn new(root: Node) -> Self {
-        let nodes = vec![root];
-        Self { nodes }
+        let mut network = Self::default();
+        network.add_node(root);
+        network
+    }
+
+    #[must_use]
+    pub fn get_node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(&id)
+    }
+
+    #[must_use]
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.nodes.contains_key(&id)
+    }
+
+    /// Iterates over the known nodes, sorted by id
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.values()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    #[must_use]
+    pub fn get_servers(&self) -> Option<Vec<NodeId>> {
+        let servers = self
+            .nodes
+            .values()
+            .filter_map(|n| {
+                if n.get_node_type() == NodeType::Server {
+                    Some(n.get_id())
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if servers.is_empty() {
+            None
+        } else {
+            Some(servers)
+        }
+    }
+
+    #[must_use]
+    pub fn get_clients(&self) -> Option<Vec<NodeId>> {
+        let clients = self
+            .nodes
+            .values()
+            .filter_map(|n| {
+                if n.get_node_type() == NodeType::Client {
+                    Some(n.get_id())
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if clients.is_empty() {
+            None
+        } else {
+            Some(clients)
+        }
+    }
+
+    /// Returns the node path searches start from
+    #[must_use]
+    pub fn get_source(&self) -> Option<NodeId> {
+        self.source
+    }
+
+    /// Sets the node path searches start from, e.g. to compute routes as seen by
+    /// another node on a view of the whole network
+    pub fn set_source(&mut self, source: NodeId) {
+        self.source = Some(source);
+    }
+
+    /// Returns whether `a` and `b` are known to be linked
+    #[must_use]
+    pub fn has_edge(&self, a: NodeId, b: NodeId) -> bool {
+        self.nodes.get(&a).is_some_and(|n| n.adjacents.contains(&b))
+            || self.nodes.get(&b).is_some_and(|n| n.adjacents.contains(&a))
+    }
+
+    /// Lists every known edge once, as `(a, b)` with `a < b`, sorted
+    #[must_use]
+    pub fn edges(&self) -> Vec<(NodeId, NodeId)> {
+        let mut edges = self
+            .nodes
+            .values()
+            .flat_map(|n| n.adjacents.iter().map(move |adj| edge_key(n.id, *adj)))
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
+
+    fn touch_edges(&mut self, node_id: NodeId, adjacents: &[NodeId], now: Instant) {
+        for adj in adjacents {
+            self.edges_seen.insert(edge_key(node_id, *adj), now);
+        }
+    }
+
+    /// Returns the last time the edge between `a` and `b` was confirmed, if it was ever learned
+    #[must_use]
+    pub fn get_edge_last_seen(&self, a: NodeId, b: NodeId) -> Option<Instant> {
+        self.edges_seen.get(&edge_key(a, b)).copied()
+    }
+
+    /// Removes nodes and edges which have not been seen for longer than `max_age`.
+    /// The source, the nodes in `keep` and the edges between the source and them never expire.
+    /// Returns the ids of the removed nodes.
+    pub(crate) fn expire(
+        &mut self,
+        now: Instant,
+        max_age: Duration,
+        keep: &HashSet<NodeId>,
+    ) -> Vec<NodeId> {
+        let root = self.source;
+        let is_stale = |seen: Instant| now.saturating_duration_since(seen) > max_age;
+        let is_protected = |id: NodeId| Some(id) == root || keep.contains(&id);
+
+        let stale_edges = self
+            .edges_seen
+            .iter()
+            .filter(|&(&(a, b), &seen)| {
+                is_stale(seen)
+                    && !((Some(a) == root && is_protected(b))
+                        || (Some(b) == root && is_protected(a)))
+            })
+            .map(|(edge, _)| *edge)
+            .collect::<Vec<_>>();
+        for (a, b) in stale_edges {
+            self.remove_edge(a, b);
+        }
+
+        let stale_nodes = self
+            .nodes
+            .values()
+            .filter(|n| !is_protected(n.id) && is_stale(n.last_seen))
+            .map(Node::get_id)
+            .collect::<Vec<_>>();
+        for id in &stale_nodes {
+            self.remove_node(*id);
+        }
+        stale_nodes
     }
 
 
     pub fn add_node_controller_view(&mut self, node_id: NodeId, node_type: NodeType, adjacents: &[NodeId]) {
-        let node = Node::new(node_id, node_type, adjacents.to_vec());
-        self.nodes.push(node);
+        self.add_node(Node::new(node_id, node_type, adjacents.to_vec()));
     }
 
+    /// Adds a node, or merges it into the known one with the same id (taking its type
+    /// and adding its adjacents), and links it both ways to the known nodes it lists
+    /// or which list it.
     pub(crate) fn add_node(&mut self, new_node: Node) {
-        for adj in new_node.get_adjacents() {
-            if let Some(node) = self.nodes.iter_mut().find(|n| n.id == *adj) {
-                match (new_node.get_node_type(), node.get_node_type()) {
-                    (_, NodeType::Drone) | (NodeType::Drone, _) => {
-                        node.add_adjacent(*adj);
-                    }
-                    _ => {}
-                }
-            }
+        let Node {
+            id,
+            kind,
+            mut adjacents,
+            last_seen,
+        } = new_node;
+        adjacents.retain(|adj| *adj != id);
+        self.source.get_or_insert(id);
+        let node = self.nodes.entry(id).or_insert_with(|| Node {
+            id,
+            kind,
+            adjacents: vec![],
+            last_seen,
+        });
+        node.kind = kind;
+        node.last_seen = last_seen;
+        for adj in &adjacents {
+            node.add_adjacent(*adj);
+        }
+        self.touch_edges(id, &adjacents, last_seen);
+
+        // complete the edges to the nodes it lists and from the nodes which listed it before it was known
+        let linked = self
+            .nodes
+            .values()
+            .filter(|n| n.id != id && (adjacents.contains(&n.id) || n.adjacents.contains(&id)))
+            .map(Node::get_id)
+            .collect::<Vec<_>>();
+        for other in linked {
+            self.link(id, other);
+        }
+    }
+
+    /// Adds `b` to the adjacents of `a` and vice versa, for the endpoints which are known
+    fn link(&mut self, a: NodeId, b: NodeId) {
+        if let Some(node) = self.nodes.get_mut(&a) {
+            node.add_adjacent(b);
+        }
+        if let Some(node) = self.nodes.get_mut(&b) {
+            node.add_adjacent(a);
         }
+    }
 
-        self.nodes.push(new_node);
+    /// Adds the edge between `a` and `b`, marking it as just confirmed
+    pub fn add_edge(&mut self, a: NodeId, b: NodeId) {
+        if a != b {
+            self.link(a, b);
+            self.edges_seen.insert(edge_key(a, b), Instant::now());
+        }
     }
 
-    pub(crate) fn remove_node(&mut self, node_id: NodeId) {
-        for n in &mut self.nodes{
-            if n.get_adjacents().contains(&node_id){
-                n.remove_adjacent(node_id);
-            }
+    /// Removes a node and every edge to it
+    pub fn remove_node(&mut self, node_id: NodeId) {
+        self.edges_seen
+            .retain(|&(a, b), _| a != node_id && b != node_id);
+        for n in self.nodes.values_mut() {
+            n.remove_adjacent(node_id);
+        }
+        let _ = self.nodes.remove(&node_id);
+    }
+
+    /// Removes the edge between `a` and `b` from both endpoints, keeping the nodes
+    pub fn remove_edge(&mut self, a: NodeId, b: NodeId) {
+        let _ = self.edges_seen.remove(&edge_key(a, b));
+        if let Some(node) = self.nodes.get_mut(&a) {
+            node.remove_adjacent(b);
         }
-        if let Some(index_to_remove) = self.nodes.iter().position(|n| n.id == node_id) {
-            let _ = self.nodes.remove(index_to_remove);
+        if let Some(node) = self.nodes.get_mut(&b) {
+            node.remove_adjacent(a);
         }
     }
 
-    /// Updates the node's adjacents with the provided list.
+    /// Adds edges from the node to each of the provided adjacents,
+    /// refreshing the last-seen time of the node and of those edges.
     /// # Errors
     /// If the node is not found, returns an error.
     pub(crate) fn update_node(&mut self, node_id: NodeId, adjacents: Vec<NodeId>) -> Result<(), NetworkError> {
-        if let Some(node) = self.nodes.iter_mut().find(|n| n.id == node_id) {
-            for adj in adjacents {
-                if !node.get_adjacents().contains(&adj) {
-                    node.add_adjacent(adj);
-                }
+        let now = Instant::now();
+        let Some(node) = self.nodes.get_mut(&node_id) else {
+            return Err(NetworkError::NodeNotFound(node_id));
+        };
+        node.last_seen = now;
+        for adj in &adjacents {
+            if *adj != node_id {
+                self.link(node_id, *adj);
             }
-
-            // teoretically no need to update neighbors of the node since they should update
-            // automatically by the protocol
-
-            return Ok(());
         }
-        Err(NetworkError::NodeNotFound(node_id))
+        self.touch_edges(node_id, &adjacents, now);
+        Ok(())
     }
 
     pub(crate) fn change_node_type(&mut self, id: NodeId, new_type: NodeType) {
-        if let Some(node) = self.nodes.iter_mut().find(|n| n.get_id() == id) {
-                node.kind = new_type;
+        if let Some(node) = self.nodes.get_mut(&id) {
+            node.kind = new_type;
         }
     }
 
 
-    /// BFS to find path to destination
+    /// Whether packets can be routed through `node_id` on their way to another node:
+    /// only drones forward packets, clients and servers can only be endpoints
+    fn is_transit(&self, node_id: NodeId) -> bool {
+        self.nodes
+            .get(&node_id)
+            .is_some_and(|n| n.kind == NodeType::Drone)
+    }
+
+    /// BFS to find path from the source to destination
     #[must_use]
     pub(crate) fn find_path(&self, destination: NodeId) -> Option<Vec<NodeId>> {
-        let start = self.nodes[0].id;
+        self.find_path_from(self.source?, destination)
+    }
+
+    /// BFS to find path from `start` to destination, going only through drones
+    #[must_use]
+    pub fn find_path_from(&self, start: NodeId, destination: NodeId) -> Option<Vec<NodeId>> {
+        self.bfs(start, destination, true)
+    }
+
+    /// Error explaining why no path from the source to destination was found:
+    /// `PathThroughNonDrone` if one exists but needs a client or server as an intermediate hop,
+    /// `PathNotFound` otherwise
+    #[must_use]
+    pub(crate) fn path_error(&self, destination: NodeId) -> NetworkError {
+        match self.source {
+            Some(start) if self.bfs(start, destination, false).is_some() => {
+                NetworkError::PathThroughNonDrone(destination)
+            }
+            _ => NetworkError::PathNotFound(destination),
+        }
+    }
+
+    fn bfs(&self, start: NodeId, destination: NodeId, drones_only: bool) -> Option<Vec<NodeId>> {
         let mut visited = HashSet::new();
         let mut queue = VecDeque::new();
         let mut parent_map = HashMap::new();
@@ -177,11 +475,14 @@ impl Network {
                 return Some(path);
             }
 
-            if let Some(node) = self.nodes.iter().find(|n| n.id == current) {
+            if current != start && drones_only && !self.is_transit(current) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&current) {
                 for neighbor in node.get_adjacents() {
                     if !visited.contains(neighbor) {
                         visited.insert(*neighbor);
-                        parent_map.insert(neighbor, current);
+                        parent_map.insert(*neighbor, current);
                         queue.push_back(*neighbor);
                     }
                 }
@@ -190,42 +491,340 @@ impl Network {
         None
     }
 
+    /// Dijkstra search to find the cheapest path to destination.
+    /// Entering a node costs one hop plus a penalty growing with its estimated drop rate
+    /// (`-ln(1 - p)`, i.e. the log of the inverse of the delivery probability), so a lossy
+    /// node is avoided whenever a slightly longer but reliable path exists.
     #[must_use]
-    pub fn get_servers(&self) -> Option<Vec<NodeId>> {
-        let servers = self.nodes.iter().filter_map(|n| {
-            if n.get_node_type() == NodeType::Server {
-                Some(n.get_id())
+    pub(crate) fn find_path_weighted(
+        &self,
+        destination: NodeId,
+        drop_rates: &HashMap<NodeId, f64>,
+    ) -> Option<Vec<NodeId>> {
+        self.cheapest_path(destination, drop_rates, &HashSet::new())
+    }
+
+    /// Computes up to `k` node-disjoint paths to destination, cheapest first.
+    /// Each path is the cheapest one avoiding the intermediate nodes of the previous ones,
+    /// so no node but the endpoints is shared between two paths.
+    #[must_use]
+    pub(crate) fn find_disjoint_paths(
+        &self,
+        destination: NodeId,
+        k: usize,
+        drop_rates: &HashMap<NodeId, f64>,
+    ) -> Vec<Vec<NodeId>> {
+        let mut excluded = HashSet::new();
+        let mut paths = Vec::new();
+        while paths.len() < k {
+            let Some(path) = self.cheapest_path(destination, drop_rates, &excluded) else {
+                break;
+            };
+            if path.len() <= 2 {
+                // a direct link can't be used by a second path
+                paths.push(path);
+                break;
+            }
+            excluded.extend(path[1..path.len() - 1].iter().copied());
+            paths.push(path);
+        }
+        paths
+    }
+
+    /// Dijkstra search used by `find_path_weighted` and, once per path, by `find_disjoint_paths`.
+    /// Only drones are crossed on the way (see `is_transit`) and the `excluded` nodes never are.
+    fn cheapest_path(
+        &self,
+        destination: NodeId,
+        drop_rates: &HashMap<NodeId, f64>,
+        excluded: &HashSet<NodeId>,
+    ) -> Option<Vec<NodeId>> {
+        let start = self.source?;
+        let mut costs = HashMap::new();
+        let mut parent_map = HashMap::new();
+        let mut heap = BinaryHeap::new();
+
+        costs.insert(start, 0.0);
+        heap.push(PathCost {
+            cost: 0.0,
+            node: start,
+        });
+
+        while let Some(PathCost {
+            cost,
+            node: current,
+        }) = heap.pop()
+        {
+            if current == destination {
+                let mut path = vec![destination];
+                let mut current = destination;
+                while let Some(&parent) = parent_map.get(&current) {
+                    path.push(parent);
+                    current = parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            if costs.get(&current).is_some_and(|c| cost > *c) {
+                continue;
             }
-            else {
-                None
+            if current != start && !self.is_transit(current) {
+                continue;
             }
-        }).collect::<Vec<_>>();
 
-        if servers.is_empty() {
-            None
-        }else {
-            Some(servers)
+            if let Some(node) = self.nodes.get(&current) {
+                for neighbor in node.get_adjacents() {
+                    if *neighbor != destination && excluded.contains(neighbor) {
+                        continue;
+                    }
+                    let penalty = if *neighbor == destination {
+                        0.0
+                    } else {
+                        let p = drop_rates
+                            .get(neighbor)
+                            .copied()
+                            .unwrap_or(0.0)
+                            .clamp(0.0, MAX_DROP_RATE);
+                        -RELIABILITY_WEIGHT * (1.0 - p).ln()
+                    };
+                    let next_cost = cost + 1.0 + penalty;
+                    if costs.get(neighbor).is_none_or(|c| next_cost < *c) {
+                        costs.insert(*neighbor, next_cost);
+                        parent_map.insert(*neighbor, current);
+                        heap.push(PathCost {
+                            cost: next_cost,
+                            node: *neighbor,
+                        });
+                    }
+                }
+            }
         }
+        None
     }
+}
 
+/// State of the DFS computing articulation points and bridges
+#[derive(Default)]
+struct Lowlink {
+    time: usize,
+    discovered: HashMap<NodeId, usize>,
+    // smallest discovery time reachable from the node's DFS subtree through one back edge
+    low: HashMap<NodeId, usize>,
+    articulation_points: BTreeSet<NodeId>,
+    bridges: Vec<(NodeId, NodeId)>,
+}
+
+/// Differences between a node's view of the network and the real topology, see `Network::diff`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkDiff {
+    /// Nodes of the real topology the view does not know
+    pub missing_nodes: Vec<NodeId>,
+    /// Nodes of the view which are not (or no longer) in the real topology
+    pub extra_nodes: Vec<NodeId>,
+    /// Edges of the real topology the view does not know, as `(a, b)` with `a < b`
+    pub missing_edges: Vec<(NodeId, NodeId)>,
+    /// Edges of the view which are not (or no longer) in the real topology
+    pub stale_edges: Vec<(NodeId, NodeId)>,
+    /// Nodes known with the wrong type, as `(id, type in the view, real type)`
+    pub wrong_types: Vec<(NodeId, NodeType, NodeType)>,
+}
 
+impl NetworkDiff {
     #[must_use]
-    pub fn get_clients(&self) -> Option<Vec<NodeId>> {
-        let clients = self.nodes.iter().filter_map(|n| {
-            if n.get_node_type() == NodeType::Client {
-                Some(n.get_id())
+    pub fn is_empty(&self) -> bool {
+        self.missing_nodes.is_empty()
+            && self.extra_nodes.is_empty()
+            && self.missing_edges.is_empty()
+            && self.stale_edges.is_empty()
+            && self.wrong_types.is_empty()
+    }
+}
+
+// Connectivity analysis: components, articulation points, bridges and the
+// client/server pairs a failure would disconnect; comparison with the real topology
+impl Network {
+    /// Adjacents of a node which are known nodes, skipping the ids not learned yet
+    fn known_adjacents(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes
+            .get(&node_id)
+            .into_iter()
+            .flat_map(|n| n.adjacents.iter().copied())
+            .filter(|adj| self.nodes.contains_key(adj))
+    }
+
+    /// Groups of nodes linked to each other, each sorted by id, ordered by their smallest id
+    #[must_use]
+    pub fn connected_components(&self) -> Vec<Vec<NodeId>> {
+        let mut visited = HashSet::new();
+        let mut components = Vec::new();
+        for &start in self.nodes.keys() {
+            if !visited.insert(start) {
+                continue;
             }
-            else {
-                None
+            let mut component = vec![start];
+            let mut queue = VecDeque::from([start]);
+            while let Some(current) = queue.pop_front() {
+                for adj in self.known_adjacents(current) {
+                    if visited.insert(adj) {
+                        component.push(adj);
+                        queue.push_back(adj);
+                    }
+                }
             }
-        }).collect::<Vec<_>>();
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
 
-        if clients.is_empty() {
-            None
-        }else {
-            Some(clients)
+    /// Nodes whose removal splits their connected component, sorted by id
+    #[must_use]
+    pub fn articulation_points(&self) -> Vec<NodeId> {
+        self.lowlink().articulation_points.into_iter().collect()
+    }
+
+    /// Edges whose removal splits their connected component, as `(a, b)` with `a < b`, sorted
+    #[must_use]
+    pub fn bridges(&self) -> Vec<(NodeId, NodeId)> {
+        let mut bridges = self.lowlink().bridges;
+        bridges.sort_unstable();
+        bridges
+    }
+
+    /// Runs Tarjan's lowlink DFS over every component
+    fn lowlink(&self) -> Lowlink {
+        let mut state = Lowlink::default();
+        for &id in self.nodes.keys() {
+            if !state.discovered.contains_key(&id) {
+                self.lowlink_visit(id, None, &mut state);
+            }
         }
+        state
+    }
 
+    fn lowlink_visit(&self, node_id: NodeId, parent: Option<NodeId>, state: &mut Lowlink) {
+        state.time += 1;
+        let discovered = state.time;
+        state.discovered.insert(node_id, discovered);
+        let mut low = discovered;
+        let mut children = 0;
+
+        for adj in self.known_adjacents(node_id) {
+            if Some(adj) == parent {
+                continue;
+            }
+            if let Some(&adj_discovered) = state.discovered.get(&adj) {
+                low = low.min(adj_discovered);
+                continue;
+            }
+            children += 1;
+            self.lowlink_visit(adj, Some(node_id), state);
+            let adj_low = state.low[&adj];
+            low = low.min(adj_low);
+            if parent.is_some() && adj_low >= discovered {
+                state.articulation_points.insert(node_id);
+            }
+            if adj_low > discovered {
+                state.bridges.push(edge_key(node_id, adj));
+            }
+        }
+
+        if parent.is_none() && children > 1 {
+            state.articulation_points.insert(node_id);
+        }
+        state.low.insert(node_id, low);
+    }
+
+    /// (client, server) pairs linked through drones which would not be anymore without `node_id`
+    #[must_use]
+    pub fn disconnected_pairs_without_node(&self, node_id: NodeId) -> Vec<(NodeId, NodeId)> {
+        let mut without = self.clone();
+        without.remove_node(node_id);
+        self.lost_client_server_pairs(&without)
+    }
+
+    /// (client, server) pairs linked through drones which would not be anymore without the edge `a` - `b`
+    #[must_use]
+    pub fn disconnected_pairs_without_edge(&self, a: NodeId, b: NodeId) -> Vec<(NodeId, NodeId)> {
+        let mut without = self.clone();
+        without.remove_edge(a, b);
+        self.lost_client_server_pairs(&without)
+    }
+
+    /// Whether crashing or removing `node_id` would leave some client unable to reach a server
+    /// it can reach now. The pairs the node itself belongs to are not counted.
+    #[must_use]
+    pub fn disconnects_clients_from_servers(&self, node_id: NodeId) -> bool {
+        !self.disconnected_pairs_without_node(node_id).is_empty()
+    }
+
+    fn lost_client_server_pairs(&self, other: &Network) -> Vec<(NodeId, NodeId)> {
+        let servers = self.get_servers().unwrap_or_default();
+        let mut lost = Vec::new();
+        for client in self.get_clients().unwrap_or_default() {
+            for server in &servers {
+                if other.contains(client)
+                    && other.contains(*server)
+                    && self.find_path_from(client, *server).is_some()
+                    && other.find_path_from(client, *server).is_none()
+                {
+                    lost.push((client, *server));
+                }
+            }
+        }
+        lost
+    }
+
+    /// Compares this view with the real topology (e.g. the one built by the initializer
+    /// with `add_node_controller_view`). Every list is sorted.
+    #[must_use]
+    pub fn diff(&self, ground_truth: &Network) -> NetworkDiff {
+        let edges = self.edges().into_iter().collect::<BTreeSet<_>>();
+        let real_edges = ground_truth.edges().into_iter().collect::<BTreeSet<_>>();
+
+        NetworkDiff {
+            missing_nodes: ground_truth
+                .nodes
+                .keys()
+                .filter(|id| !self.contains(**id))
+                .copied()
+                .collect(),
+            extra_nodes: self
+                .nodes
+                .keys()
+                .filter(|id| !ground_truth.contains(**id))
+                .copied()
+                .collect(),
+            missing_edges: real_edges.difference(&edges).copied().collect(),
+            stale_edges: edges.difference(&real_edges).copied().collect(),
+            wrong_types: self
+                .nodes
+                .values()
+                .filter_map(|n| {
+                    let real = ground_truth.get_node(n.id)?.kind;
+                    (real != n.kind).then_some((n.id, n.kind, real))
+                })
+                .collect(),
+        }
+    }
+
+    /// How close this view is to the real topology, from 0 (nothing in common) to 1 (identical):
+    /// the share of nodes (with their type) and edges known correctly among the ones in either network
+    #[must_use]
+    pub fn convergence_score(&self, ground_truth: &Network) -> f64 {
+        let diff = self.diff(ground_truth);
+        let edges = self.edges().len();
+        let nodes = self.nodes.len();
+        let correct_nodes = nodes - diff.extra_nodes.len() - diff.wrong_types.len();
+        let correct_edges = edges - diff.stale_edges.len();
+        let total = nodes + diff.missing_nodes.len() + edges + diff.missing_edges.len();
+        if total == 0 {
+            return 1.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let score = (correct_nodes + correct_edges) as f64 / total as f64;
+        score
     }
 }
 
@@ -242,8 +841,8 @@ mod tests {
         let new_node = Node::new(2, NodeType::Client, vec![1]);
         network.add_node(new_node);
 
-        assert_eq!(network.nodes.len(), 2);
-        assert!(network.nodes.iter().any(|n| n.id == 2));
+        assert_eq!(network.len(), 2);
+        assert!(network.contains(2));
     }
 
     #[test]
@@ -257,8 +856,8 @@ mod tests {
 
         network.remove_node(2);
 
-        assert_eq!(network.nodes.len(), 1);
-        assert!(!network.nodes.iter().any(|n| n.id == 2));
+        assert_eq!(network.len(), 1);
+        assert!(!network.contains(2));
     }
 
     #[test]
@@ -272,7 +871,7 @@ mod tests {
 
         network.update_node(1, vec![3]).unwrap();
 
-        assert!(network.nodes[0].get_adjacents().contains(&3));
+        assert!(network.get_node(1).unwrap().get_adjacents().contains(&3));
     }
 
     #[test]
@@ -283,7 +882,10 @@ mod tests {
 
         network.change_node_type(1, NodeType::Drone);
 
-        assert_eq!(network.nodes[0].get_node_type(), NodeType::Drone);
+        assert_eq!(
+            network.get_node(1).unwrap().get_node_type(),
+            NodeType::Drone
+        );
     }
 
     #[test]
@@ -292,7 +894,7 @@ mod tests {
         let root = Node::new(1, NodeType::Client, vec![2, 3]);
         let mut network = Network::new(root);
 
-        let node2 = Node::new(2, NodeType::Client, vec![1, 4]);
+        let node2 = Node::new(2, NodeType::Drone, vec![1, 4]);
         let node3 = Node::new(3, NodeType::Client, vec![1]);
         let node4 = Node::new(4, NodeType::Client, vec![2]);
 
@@ -398,4 +1000,382 @@ mod tests {
         let path_after_removal = network.find_path(3);
         assert!(path_after_removal.is_none());
     }
+
+    #[test]
+    /// Tests that `find_path_weighted` avoids a lossy drone when a reliable detour exists
+    fn test_find_path_weighted_avoids_lossy_node() {
+        let root = Node::new(1, NodeType::Client, vec![2, 3]);
+        let mut network = Network::new(root);
+
+        network.add_node(Node::new(2, NodeType::Drone, vec![1, 6]));
+        network.add_node(Node::new(3, NodeType::Drone, vec![1, 4]));
+        network.add_node(Node::new(4, NodeType::Drone, vec![3, 5]));
+        network.add_node(Node::new(5, NodeType::Drone, vec![4, 6]));
+        network.add_node(Node::new(6, NodeType::Server, vec![2, 5]));
+
+        let no_stats = HashMap::new();
+        assert_eq!(
+            network.find_path_weighted(6, &no_stats),
+            Some(vec![1, 2, 6])
+        );
+
+        let drop_rates = HashMap::from([(2, 0.9)]);
+        assert_eq!(
+            network.find_path_weighted(6, &drop_rates),
+            Some(vec![1, 3, 4, 5, 6])
+        );
+
+        let drop_rates = HashMap::from([(2, 0.05)]);
+        assert_eq!(
+            network.find_path_weighted(6, &drop_rates),
+            Some(vec![1, 2, 6])
+        );
+    }
+
+    #[test]
+    /// Tests that nodes and edges not seen for too long are expired, except protected ones
+    fn test_expire_stale_entries() {
+        let root = Node::new(1, NodeType::Client, vec![]);
+        let mut network = Network::new(root);
+
+        network.update_node(1, vec![2]).unwrap();
+        network.add_node(Node::new(2, NodeType::Drone, vec![1, 3]));
+        network.add_node(Node::new(3, NodeType::Server, vec![2]));
+
+        let max_age = Duration::from_secs(10);
+        let keep = HashSet::from([2]);
+
+        let removed = network.expire(Instant::now(), max_age, &keep);
+        assert!(removed.is_empty());
+        assert_eq!(network.len(), 3);
+
+        let later = Instant::now() + Duration::from_secs(11);
+        let removed = network.expire(later, max_age, &keep);
+        assert_eq!(removed, vec![3]);
+        assert_eq!(network.len(), 2);
+        assert!(network.get_edge_last_seen(2, 3).is_none());
+        assert!(!network.get_node(2).unwrap().get_adjacents().contains(&3));
+        assert!(network.get_edge_last_seen(1, 2).is_some());
+        assert!(network.get_node(1).unwrap().get_adjacents().contains(&2));
+    }
+
+    #[test]
+    /// Tests that `find_disjoint_paths` returns paths sharing only their endpoints
+    fn test_find_disjoint_paths() {
+        let root = Node::new(1, NodeType::Client, vec![2, 3]);
+        let mut network = Network::new(root);
+
+        network.add_node(Node::new(2, NodeType::Drone, vec![1, 4, 5]));
+        network.add_node(Node::new(3, NodeType::Drone, vec![1, 5]));
+        network.add_node(Node::new(4, NodeType::Drone, vec![2, 6]));
+        network.add_node(Node::new(5, NodeType::Drone, vec![2, 3, 6]));
+        network.add_node(Node::new(6, NodeType::Server, vec![4, 5]));
+
+        let paths = network.find_disjoint_paths(6, 3, &HashMap::new());
+        assert_eq!(paths.len(), 2);
+        let first: HashSet<_> = paths[0][1..paths[0].len() - 1].iter().collect();
+        let second: HashSet<_> = paths[1][1..paths[1].len() - 1].iter().collect();
+        assert!(first.is_disjoint(&second));
+        assert!(paths.iter().all(|p| p[0] == 1 && p[p.len() - 1] == 6));
+
+        let direct = network.find_disjoint_paths(2, 3, &HashMap::new());
+        assert_eq!(direct, vec![vec![1, 2]]);
+    }
+
+    /// Checks that every edge between known nodes is listed by both endpoints, once
+    fn assert_consistent(network: &Network) {
+        for node in network.nodes() {
+            let adjacents = node.get_adjacents();
+            assert!(!adjacents.contains(&node.id), "self loop on {}", node.id);
+            let unique: HashSet<_> = adjacents.iter().collect();
+            assert_eq!(
+                unique.len(),
+                adjacents.len(),
+                "duplicated adjacents on {}",
+                node.id
+            );
+            for adj in adjacents {
+                if let Some(other) = network.get_node(*adj) {
+                    assert!(
+                        other.get_adjacents().contains(&node.id),
+                        "edge {} - {adj} is one-sided",
+                        node.id
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    /// Tests that adding a node links it to the nodes it lists and to the ones which listed it
+    fn test_add_node_links_both_ways() {
+        let root = Node::new(1, NodeType::Client, vec![3]);
+        let mut network = Network::new(root);
+
+        network.add_node(Node::new(2, NodeType::Drone, vec![1]));
+        assert!(network.get_node(1).unwrap().get_adjacents().contains(&2));
+        assert!(!network.get_node(2).unwrap().get_adjacents().contains(&2));
+
+        // 3 was listed by the root before being known
+        network.add_node(Node::new(3, NodeType::Drone, vec![]));
+        assert_eq!(network.get_node(3).unwrap().get_adjacents(), &vec![1]);
+        assert!(network.has_edge(3, 1));
+        assert_eq!(network.edges(), vec![(1, 2), (1, 3)]);
+        assert_consistent(&network);
+    }
+
+    #[test]
+    /// Tests that adding a known node again merges it instead of duplicating it
+    fn test_add_node_merges_existing() {
+        let root = Node::new(1, NodeType::Client, vec![2]);
+        let mut network = Network::new(root);
+
+        network.add_node(Node::new(2, NodeType::Client, vec![1]));
+        network.add_node(Node::new(2, NodeType::Drone, vec![1, 3]));
+        assert_eq!(network.len(), 2);
+        let node = network.get_node(2).unwrap();
+        assert_eq!(node.get_node_type(), NodeType::Drone);
+        assert_eq!(node.get_adjacents(), &vec![1, 3]);
+        assert_consistent(&network);
+    }
+
+    #[test]
+    /// Tests explicit edge operations and that they keep the graph symmetric
+    fn test_add_remove_edge() {
+        let root = Node::new(1, NodeType::Client, vec![]);
+        let mut network = Network::new(root);
+        network.add_node(Node::new(2, NodeType::Drone, vec![]));
+        network.add_node(Node::new(3, NodeType::Server, vec![]));
+
+        network.add_edge(1, 2);
+        network.add_edge(3, 2);
+        network.add_edge(2, 2);
+        network.add_edge(2, 3);
+        assert_eq!(network.edges(), vec![(1, 2), (2, 3)]);
+        assert!(network.get_edge_last_seen(3, 2).is_some());
+        assert_eq!(network.find_path(3), Some(vec![1, 2, 3]));
+        assert_consistent(&network);
+
+        network.remove_edge(3, 2);
+        assert!(!network.has_edge(2, 3));
+        assert!(network.get_edge_last_seen(2, 3).is_none());
+        assert_eq!(network.find_path(3), None);
+
+        network.update_node(3, vec![1]).unwrap();
+        assert!(network.get_node(1).unwrap().get_adjacents().contains(&3));
+        network.remove_node(1);
+        assert!(network.edges().is_empty());
+        assert_consistent(&network);
+    }
+
+    #[test]
+    /// Tests that path searches start from the configured source
+    fn test_path_search_source() {
+        let mut network = Network::default();
+        assert_eq!(network.find_path(1), None);
+
+        network.add_node_controller_view(5, NodeType::Drone, &[1, 6]);
+        network.add_node_controller_view(1, NodeType::Client, &[5]);
+        network.add_node_controller_view(6, NodeType::Server, &[5]);
+        assert_eq!(network.get_source(), Some(5));
+        assert_eq!(network.find_path(6), Some(vec![5, 6]));
+
+        network.set_source(6);
+        assert_eq!(network.find_path(1), Some(vec![6, 5, 1]));
+        assert_eq!(
+            network.find_path_weighted(1, &HashMap::new()),
+            Some(vec![6, 5, 1])
+        );
+        assert_eq!(network.find_path_from(1, 6), Some(vec![1, 5, 6]));
+        assert_eq!(
+            network.nodes().map(Node::get_id).collect::<Vec<_>>(),
+            vec![1, 5, 6]
+        );
+        assert_consistent(&network);
+    }
+
+    #[test]
+    /// Tests that a sequence of flood-like updates, removals and expirations keeps the graph consistent
+    fn test_consistency_under_updates() {
+        let root = Node::new(1, NodeType::Client, vec![]);
+        let mut network = Network::new(root);
+
+        for i in 2..20u8 {
+            let kind = if i % 5 == 0 {
+                NodeType::Server
+            } else {
+                NodeType::Drone
+            };
+            network.add_node(Node::new(i, kind, vec![i - 1, (i * 7) % 19 + 1]));
+            if i % 3 == 0 {
+                let _ = network.update_node(i / 2, vec![i, 1]);
+            }
+            if i % 4 == 0 {
+                network.remove_edge(i, i - 1);
+            }
+            if i % 6 == 0 {
+                network.remove_node(i - 2);
+            }
+            assert_consistent(&network);
+        }
+
+        let removed = network.expire(
+            Instant::now() + Duration::from_secs(100),
+            Duration::from_secs(10),
+            &HashSet::new(),
+        );
+        assert!(!removed.contains(&1));
+        assert_eq!(network.len(), 1);
+        assert_consistent(&network);
+    }
+
+    #[test]
+    /// Tests that clients and servers are never used as intermediate hops
+    fn test_paths_only_traverse_drones() {
+        let root = Node::new(1, NodeType::Client, vec![2, 3]);
+        let mut network = Network::new(root);
+
+        network.add_node(Node::new(2, NodeType::Server, vec![1, 4]));
+        network.add_node(Node::new(3, NodeType::Drone, vec![1, 5]));
+        network.add_node(Node::new(5, NodeType::Drone, vec![3, 6]));
+        network.add_node(Node::new(6, NodeType::Drone, vec![5, 4]));
+        network.add_node(Node::new(4, NodeType::Server, vec![2, 6]));
+
+        // the shortest path 1-2-4 goes through server 2
+        assert_eq!(network.find_path(4), Some(vec![1, 3, 5, 6, 4]));
+        assert_eq!(
+            network.find_path_weighted(4, &HashMap::new()),
+            Some(vec![1, 3, 5, 6, 4])
+        );
+        assert_eq!(
+            network.find_disjoint_paths(4, 2, &HashMap::new()),
+            vec![vec![1, 3, 5, 6, 4]]
+        );
+        assert_eq!(network.find_path(2), Some(vec![1, 2]));
+
+        // without the drone detour, 4 is reachable only through server 2
+        network.remove_edge(5, 6);
+        assert_eq!(network.find_path(4), None);
+        assert_eq!(network.find_path_weighted(4, &HashMap::new()), None);
+        assert!(matches!(
+            network.path_error(4),
+            NetworkError::PathThroughNonDrone(4)
+        ));
+        assert!(matches!(
+            network.path_error(9),
+            NetworkError::PathNotFound(9)
+        ));
+    }
+
+    /// Client 1 and server 9 linked by a drone diamond 2-3/4-5, plus a separate drone pair
+    fn create_critical_network() -> Network {
+        let mut network = Network::default();
+        network.add_node_controller_view(1, NodeType::Client, &[2]);
+        network.add_node_controller_view(2, NodeType::Drone, &[1, 3, 4]);
+        network.add_node_controller_view(3, NodeType::Drone, &[2, 5]);
+        network.add_node_controller_view(4, NodeType::Drone, &[2, 5]);
+        network.add_node_controller_view(5, NodeType::Drone, &[3, 4, 9]);
+        network.add_node_controller_view(9, NodeType::Server, &[5]);
+        network.add_node_controller_view(7, NodeType::Drone, &[8]);
+        network.add_node_controller_view(8, NodeType::Drone, &[7]);
+        network
+    }
+
+    #[test]
+    /// Tests connected components, articulation points and bridges
+    fn test_critical_nodes_and_edges() {
+        let network = create_critical_network();
+
+        assert_eq!(
+            network.connected_components(),
+            vec![vec![1, 2, 3, 4, 5, 9], vec![7, 8]]
+        );
+        assert_eq!(network.articulation_points(), vec![2, 5]);
+        assert_eq!(network.bridges(), vec![(1, 2), (5, 9), (7, 8)]);
+
+        let mut cycle = create_critical_network();
+        cycle.add_edge(1, 9);
+        cycle.add_edge(7, 3);
+        assert_eq!(cycle.connected_components().len(), 1);
+        assert_eq!(cycle.articulation_points(), vec![3, 7]);
+        assert_eq!(cycle.bridges(), vec![(3, 7), (7, 8)]);
+    }
+
+    #[test]
+    /// Tests which removals would disconnect a client from a server
+    fn test_client_server_disconnection() {
+        let mut network = create_critical_network();
+
+        assert!(!network.disconnects_clients_from_servers(3));
+        assert!(!network.disconnects_clients_from_servers(7));
+        assert_eq!(network.disconnected_pairs_without_node(2), vec![(1, 9)]);
+        assert!(network.disconnects_clients_from_servers(5));
+        // a client or server going away does not count as a disconnection
+        assert!(!network.disconnects_clients_from_servers(1));
+        assert!(!network.disconnects_clients_from_servers(9));
+
+        assert!(network.disconnected_pairs_without_edge(3, 5).is_empty());
+        assert_eq!(network.disconnected_pairs_without_edge(9, 5), vec![(1, 9)]);
+
+        // a link through another client is not a usable alternative
+        network.add_node_controller_view(6, NodeType::Client, &[2, 9]);
+        assert!(!network.articulation_points().contains(&5));
+        assert!(network.disconnects_clients_from_servers(5));
+    }
+
+    #[test]
+    /// Tests the diff of a partial and outdated view against the real topology
+    fn test_diff_against_ground_truth() {
+        let truth = create_critical_network();
+        assert!(truth.diff(&truth).is_empty());
+        assert!((truth.convergence_score(&truth) - 1.0).abs() < f64::EPSILON);
+
+        let mut view = Network::new(Node::new(1, NodeType::Client, vec![2]));
+        view.add_node(Node::new(2, NodeType::Drone, vec![1, 3, 6]));
+        view.add_node(Node::new(3, NodeType::Drone, vec![2, 5]));
+        view.add_node(Node::new(5, NodeType::Client, vec![3]));
+        view.add_node(Node::new(6, NodeType::Drone, vec![2]));
+
+        let diff = view.diff(&truth);
+        assert_eq!(diff.missing_nodes, vec![4, 7, 8, 9]);
+        assert_eq!(diff.extra_nodes, vec![6]);
+        assert_eq!(diff.missing_edges, vec![(2, 4), (4, 5), (5, 9), (7, 8)]);
+        assert_eq!(diff.stale_edges, vec![(2, 6)]);
+        assert_eq!(
+            diff.wrong_types,
+            vec![(5, NodeType::Client, NodeType::Drone)]
+        );
+
+        // 3 of 5 nodes and 3 of 4 edges right, out of 9 nodes and 8 edges overall
+        let score = view.convergence_score(&truth);
+        assert!((score - 6.0 / 17.0).abs() < 1e-9, "score {score}");
+        assert!(
+            (Network::default().convergence_score(&Network::default()) - 1.0).abs() < f64::EPSILON
+        );
+        assert!(Network::default().convergence_score(&truth).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    /// Tests that the score grows as the view learns the topology and drops after a change
+    fn test_convergence_after_topology_change() {
+        let mut truth = create_critical_network();
+        let mut view = Network::default();
+        let mut last_score = view.convergence_score(&truth);
+        for node in truth.nodes().cloned().collect::<Vec<_>>() {
+            view.add_node(node);
+            let score = view.convergence_score(&truth);
+            assert!(score > last_score);
+            last_score = score;
+        }
+        assert!(view.diff(&truth).is_empty());
+
+        truth.remove_node(4);
+        let diff = view.diff(&truth);
+        assert_eq!(diff.extra_nodes, vec![4]);
+        assert_eq!(diff.stale_edges, vec![(2, 4), (4, 5)]);
+        assert!(view.convergence_score(&truth) < 1.0);
+
+        view.remove_node(4);
+        assert!(view.diff(&truth).is_empty());
+    }
 }
@@ -0,0 +1,305 @@
+use crate::network::Network;
+
+use std::collections::{BTreeSet, HashMap};
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+use serde_json::json;
+use wg_internal::{network::NodeId, packet::NodeType};
+
+/// Optional per-edge labels (e.g. PDR or traffic counts), keyed by the edge's endpoints in either order
+pub type EdgeLabels = HashMap<(NodeId, NodeId), String>;
+
+/// Fill colour of nodes listed as adjacents but not known yet
+const UNKNOWN_COLOR: &str = "#bab0ac";
+/// Distance between two nodes on the SVG layout circle
+const SVG_NODE_SPACING: f64 = 70.0;
+const SVG_NODE_RADIUS: f64 = 18.0;
+const SVG_MARGIN: f64 = 60.0;
+
+fn type_name(node_type: Option<NodeType>) -> &'static str {
+    match node_type {
+        Some(NodeType::Client) => "Client",
+        Some(NodeType::Drone) => "Drone",
+        Some(NodeType::Server) => "Server",
+        None => "Unknown",
+    }
+}
+
+fn type_color(node_type: Option<NodeType>) -> &'static str {
+    match node_type {
+        Some(NodeType::Client) => "#4e79a7",
+        Some(NodeType::Drone) => "#f28e2b",
+        Some(NodeType::Server) => "#59a14f",
+        None => UNKNOWN_COLOR,
+    }
+}
+
+fn edge_label(labels: Option<&EdgeLabels>, a: NodeId, b: NodeId) -> Option<&str> {
+    let labels = labels?;
+    labels
+        .get(&(a, b))
+        .or_else(|| labels.get(&(b, a)))
+        .map(String::as_str)
+}
+
+/// Escapes a string for a double quoted DOT attribute
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Escapes a string for SVG text content and attributes
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Network {
+    /// Every node appearing in the network with its type, `None` for the ids which are
+    /// listed as adjacents but not known yet, sorted by id
+    fn export_nodes(&self) -> Vec<(NodeId, Option<NodeType>)> {
+        let ids = self
+            .nodes()
+            .map(|n| n.get_id())
+            .chain(self.edges().into_iter().flat_map(|(a, b)| [a, b]))
+            .collect::<BTreeSet<_>>();
+        ids.into_iter()
+            .map(|id| (id, self.get_node(id).map(|n| n.get_node_type())))
+            .collect()
+    }
+
+    /// Renders the network as an undirected Graphviz graph, nodes filled by type
+    #[must_use]
+    pub fn to_dot(&self, labels: Option<&EdgeLabels>) -> String {
+        let mut dot = String::from(
+            "graph network {\n    node [shape=circle, style=filled, fontcolor=white];\n",
+        );
+        for (id, node_type) in self.export_nodes() {
+            let _ = writeln!(
+                dot,
+                "    {id} [label=\"{id}\", fillcolor=\"{}\", tooltip=\"{}\"];",
+                type_color(node_type),
+                type_name(node_type)
+            );
+        }
+        for (a, b) in self.edges() {
+            match edge_label(labels, a, b) {
+                Some(label) => {
+                    let _ = writeln!(dot, "    {a} -- {b} [label=\"{}\"];", escape_dot(label));
+                }
+                None => {
+                    let _ = writeln!(dot, "    {a} -- {b};");
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Serializes the network as `{"nodes": [{"id", "type", "adjacents"}], "edges": [{"from", "to", "label"?}]}`
+    #[must_use]
+    pub fn to_json(&self, labels: Option<&EdgeLabels>) -> String {
+        let nodes = self
+            .export_nodes()
+            .into_iter()
+            .map(|(id, node_type)| {
+                let adjacents = self
+                    .get_node(id)
+                    .map(|n| n.get_adjacents().clone())
+                    .unwrap_or_default();
+                json!({ "id": id, "type": type_name(node_type), "adjacents": adjacents })
+            })
+            .collect::<Vec<_>>();
+        let edges = self
+            .edges()
+            .into_iter()
+            .map(|(a, b)| match edge_label(labels, a, b) {
+                Some(label) => json!({ "from": a, "to": b, "label": label }),
+                None => json!({ "from": a, "to": b }),
+            })
+            .collect::<Vec<_>>();
+        json!({ "nodes": nodes, "edges": edges }).to_string()
+    }
+
+    /// Renders the network as a standalone SVG image. Nodes are laid out on a circle,
+    /// grouped by type (clients, drones, servers), and filled by type; a legend is drawn
+    /// in the top left corner.
+    #[must_use]
+    pub fn to_svg(&self, labels: Option<&EdgeLabels>) -> String {
+        let mut nodes = self.export_nodes();
+        let type_order = |t: Option<NodeType>| match t {
+            Some(NodeType::Client) => 0,
+            Some(NodeType::Drone) => 1,
+            Some(NodeType::Server) => 2,
+            None => 3,
+        };
+        nodes.sort_by_key(|&(id, t)| (type_order(t), id));
+
+        #[allow(clippy::cast_precision_loss)]
+        let count = nodes.len() as f64;
+        let radius = (SVG_NODE_SPACING * count / (2.0 * PI)).max(SVG_NODE_SPACING);
+        let center = radius + SVG_MARGIN;
+        let size = 2.0 * center;
+        let positions = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &(id, _))| {
+                #[allow(clippy::cast_precision_loss)]
+                let angle = 2.0 * PI * i as f64 / count - PI / 2.0;
+                (
+                    id,
+                    (center + radius * angle.cos(), center + radius * angle.sin()),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size:.0}\" height=\"{size:.0}\" viewBox=\"0 0 {size:.0} {size:.0}\" font-family=\"sans-serif\" font-size=\"12\">"
+        );
+        let _ = writeln!(
+            svg,
+            "  <rect width=\"100%\" height=\"100%\" fill=\"white\"/>"
+        );
+
+        for (a, b) in self.edges() {
+            let ((x1, y1), (x2, y2)) = (positions[&a], positions[&b]);
+            let _ = writeln!(
+                svg,
+                "  <line x1=\"{x1:.1}\" y1=\"{y1:.1}\" x2=\"{x2:.1}\" y2=\"{y2:.1}\" stroke=\"#888888\" stroke-width=\"1.5\"/>"
+            );
+            if let Some(label) = edge_label(labels, a, b) {
+                let (x, y) = (f64::midpoint(x1, x2), f64::midpoint(y1, y2));
+                let _ = writeln!(
+                    svg,
+                    "  <text x=\"{x:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#333333\">{}</text>",
+                    y - 4.0,
+                    escape_xml(label)
+                );
+            }
+        }
+
+        for &(id, node_type) in &nodes {
+            let (x, y) = positions[&id];
+            let _ = writeln!(
+                svg,
+                "  <circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"{SVG_NODE_RADIUS}\" fill=\"{}\"><title>{} {id}</title></circle>",
+                type_color(node_type),
+                type_name(node_type)
+            );
+            let _ = writeln!(
+                svg,
+                "  <text x=\"{x:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"white\" font-weight=\"bold\">{id}</text>",
+                y + 4.0
+            );
+        }
+
+        for (i, node_type) in [
+            Some(NodeType::Client),
+            Some(NodeType::Drone),
+            Some(NodeType::Server),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            #[allow(clippy::cast_precision_loss)]
+            let y = 20.0 + 20.0 * i as f64;
+            let _ = writeln!(
+                svg,
+                "  <rect x=\"10\" y=\"{:.0}\" width=\"12\" height=\"12\" fill=\"{}\"/><text x=\"28\" y=\"{y:.0}\">{}</text>",
+                y - 10.0,
+                type_color(node_type),
+                type_name(node_type)
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod network_export_tests {
+    use super::*;
+
+    fn create_test_network() -> Network {
+        let mut network = Network::default();
+        network.add_node_controller_view(1, NodeType::Client, &[2]);
+        network.add_node_controller_view(2, NodeType::Drone, &[1, 3, 7]);
+        network.add_node_controller_view(3, NodeType::Server, &[2]);
+        network
+    }
+
+    fn labels() -> EdgeLabels {
+        HashMap::from([
+            ((2, 1), String::from("pdr 0.1")),
+            ((2, 3), String::from("<42> \"pkts\"")),
+        ])
+    }
+
+    #[test]
+    /// Tests the DOT output, with and without edge labels
+    fn test_to_dot() {
+        let network = create_test_network();
+
+        let dot = network.to_dot(None);
+        assert!(dot.starts_with("graph network {"));
+        assert!(dot.contains("1 [label=\"1\", fillcolor=\"#4e79a7\", tooltip=\"Client\"];"));
+        assert!(dot.contains("2 [label=\"2\", fillcolor=\"#f28e2b\", tooltip=\"Drone\"];"));
+        assert!(dot.contains("3 [label=\"3\", fillcolor=\"#59a14f\", tooltip=\"Server\"];"));
+        assert!(dot.contains(&format!(
+            "7 [label=\"7\", fillcolor=\"{UNKNOWN_COLOR}\", tooltip=\"Unknown\"];"
+        )));
+        assert!(dot.contains("    1 -- 2;\n"));
+        assert!(dot.contains("    2 -- 7;\n"));
+        assert!(dot.trim_end().ends_with('}'));
+
+        let dot = network.to_dot(Some(&labels()));
+        assert!(dot.contains("1 -- 2 [label=\"pdr 0.1\"];"));
+        assert!(dot.contains("2 -- 3 [label=\"<42> \\\"pkts\\\"\"];"));
+        assert!(dot.contains("    2 -- 7;\n"));
+    }
+
+    #[test]
+    /// Tests that the JSON output lists every node and edge
+    fn test_to_json() {
+        let network = create_test_network();
+
+        let value: serde_json::Value =
+            serde_json::from_str(&network.to_json(Some(&labels()))).unwrap();
+        let nodes = value["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(
+            nodes[1],
+            json!({ "id": 2, "type": "Drone", "adjacents": [1, 3, 7] })
+        );
+        assert_eq!(nodes[3]["type"], "Unknown");
+
+        let edges = value["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), 3);
+        assert_eq!(edges[0], json!({ "from": 1, "to": 2, "label": "pdr 0.1" }));
+        assert_eq!(edges[2], json!({ "from": 2, "to": 7 }));
+    }
+
+    #[test]
+    /// Tests that the SVG output draws every node and edge and escapes the labels
+    fn test_to_svg() {
+        let network = create_test_network();
+
+        let svg = network.to_svg(Some(&labels()));
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<circle").count(), 4);
+        assert_eq!(svg.matches("<line").count(), 3);
+        assert!(svg.contains("fill=\"#59a14f\"><title>Server 3</title>"));
+        assert!(svg.contains("&lt;42&gt; &quot;pkts&quot;"));
+        assert!(!svg.contains("<42>"));
+
+        let empty = Network::default().to_svg(None);
+        assert_eq!(empty.matches("<circle").count(), 0);
+    }
+}
@@ -1,7 +1,25 @@
-use crate::{network::NetworkError, types::Command, FragmentAssembler, RoutingHandler};
+use crate::{
+    FragmentAssembler, RoutingHandler,
+    envelope::{Codec, Envelope, EnvelopeError},
+    network::NetworkError,
+    types::{Command, NodeCommand, NodeEvent},
+};
 
-use crossbeam_channel::{select_biased, Receiver};
-use wg_internal::{network::NodeId, packet::{Packet, PacketType}};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, after, never, select_biased, tick};
+use wg_internal::{
+    network::NodeId,
+    packet::{NackType, Packet, PacketType},
+};
+
+/// How often `run` checks the routing buffer for fragments to retransmit,
+/// the network view for stale entries and the assembler for idle messages
+const TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Minimum time between two progress events for the same incoming message
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_millis(500);
+/// Default time given to outgoing sessions to complete once a node is asked to shut down
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub trait Processor: Send {
     fn controller_recv(&self) -> &Receiver<Box<dyn Command>>;
@@ -12,6 +30,26 @@ pub trait Processor: Send {
     fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, session_id: u64);
     fn handle_command(&mut self, cmd: Box<dyn Command>) -> bool;
 
+    /// How often `run` calls `on_tick`, `None` (the default) disables the hook
+    fn tick_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Periodic hook for node specific timers such as cache expiry or heartbeats.
+    /// Routing retransmissions and reassembly timeouts are already handled by `run`.
+    fn on_tick(&mut self, _now: Instant) {}
+
+    /// How long `shutdown` waits for the outgoing sessions before failing them
+    fn shutdown_timeout(&self) -> Duration {
+        DEFAULT_SHUTDOWN_TIMEOUT
+    }
+
+    /// Handles a verified message. Nodes which negotiate a codec other than JSON
+    /// override this to decode the payload according to `envelope.codec`.
+    fn handle_envelope(&mut self, envelope: Envelope, from: NodeId, session_id: u64) {
+        self.handle_msg(envelope.payload, from, session_id);
+    }
+
     /// Handles a packet in a standard way
     /// # Errors
     /// returns an Errors if handling fails
@@ -23,16 +61,47 @@ pub trait Processor: Send {
                 let mut shr = pkt.routing_header.clone();
                     shr.reverse();
                 self.routing_handler().send_ack(shr, pkt.session_id, idx)?;
-                if let Some(msg) = self.assembler().add_fragment(
-                    fragment,
-                    pkt.session_id,
-                    pkt.routing_header.hops[0],
-                ) {
-                    self.handle_msg(msg, pkt.routing_header.hops[0], pkt.session_id);
+                let from = pkt.routing_header.hops[0];
+                self.routing_handler()
+                    .stats_mut()
+                    .record_fragment_received(&fragment);
+                if let Some(msg) = self
+                    .assembler()
+                    .add_fragment(fragment, pkt.session_id, from)
+                {
+                    match Envelope::from_bytes(&msg) {
+                        Ok(envelope) => {
+                            self.routing_handler().stats_mut().record_message_received();
+                            self.handle_envelope(envelope, from, pkt.session_id);
+                        }
+                        // raw serde_json payload from a peer which predates the envelope
+                        Err(EnvelopeError::UnsupportedVersion(_) | EnvelopeError::TooShort(_))
+                            if serde_json::from_slice::<serde::de::IgnoredAny>(&msg).is_ok() =>
+                        {
+                            self.routing_handler().stats_mut().record_message_received();
+                            self.handle_envelope(
+                                Envelope::new(Codec::Json, msg),
+                                from,
+                                pkt.session_id,
+                            );
+                        }
+                        Err(e) => {
+                            let router = self.routing_handler();
+                            let _ = router.notify_controller(NodeEvent::MessageCorrupted {
+                                notification_from: router.get_id(),
+                                from,
+                                session_id: pkt.session_id,
+                                error: e.to_string(),
+                            });
+                        }
+                    }
                 }
+                let _ = self.report_abandoned_messages();
             }
             PacketType::Ack(ack) => {
-                router.handle_ack(&ack, pkt.session_id, pkt.routing_header.hops[0]);
+                // a failed window refill is not fatal: the fragments left unsent
+                // are picked up by the retransmission timer
+                let _ = router.handle_ack(&ack, pkt.session_id, pkt.routing_header.hops[0]);
             }
             PacketType::Nack(nack) => {
                 router.handle_nack(&nack, pkt.session_id, pkt.routing_header.hops[0])?;
@@ -47,13 +116,155 @@ pub trait Processor: Send {
         Ok(())
     }
 
+    /// Sends the node's traffic counters to the controller, in answer to `NodeCommand::GetStats`
+    fn report_stats(&mut self) {
+        let router = self.routing_handler();
+        let _ = router.notify_controller(NodeEvent::Stats {
+            notification_from: router.get_id(),
+            stats: router.get_stats(),
+        });
+    }
+
+    /// Sends the node's network view to the controller, in answer to `NodeCommand::GetNetworkView`
+    fn report_network_view(&mut self) {
+        let router = self.routing_handler();
+        let _ = router.notify_controller(NodeEvent::NetworkView {
+            notification_from: router.get_id(),
+            network: router.get_network_view().clone(),
+        });
+    }
+
+    /// Notifies the controller about the incomplete messages dropped by the assembler
+    /// and returns how many they were
+    fn report_abandoned_messages(&mut self) -> usize {
+        let abandoned = self.assembler().take_abandoned();
+        let count = abandoned.len();
+        let router = self.routing_handler();
+        for message in abandoned {
+            let _ = router.notify_controller(NodeEvent::MessageAbandoned {
+                notification_from: router.get_id(),
+                from: message.sender,
+                session_id: message.session_id,
+                received_fragments: message.received_fragments,
+                total_fragments: message.total_fragments,
+            });
+        }
+        count
+    }
+
+    /// Notifies the controller about the incoming messages which made progress
+    fn report_progress(&mut self, now: Instant) {
+        let updates = self
+            .assembler()
+            .progress_updates(now, PROGRESS_REPORT_INTERVAL);
+        let router = self.routing_handler();
+        for progress in updates {
+            let _ = router.notify_controller(NodeEvent::ReassemblyProgress {
+                notification_from: router.get_id(),
+                from: progress.sender,
+                session_id: progress.session_id,
+                received_fragments: progress.received_fragments,
+                total_fragments: progress.total_fragments,
+                received_bytes: progress.received_bytes,
+            });
+        }
+    }
+
+    /// Drains the node before `run` returns, on `Shutdown` or when a packet cannot be handled.
+    /// Commands are ignored and the fragments of incoming messages are refused with a `Dropped`
+    /// Nack, so their sender retries or gives up, while the outgoing sessions get
+    /// `shutdown_timeout` to be acknowledged; the ones still active after it (or after a second
+    /// `Shutdown`) are failed. Flood requests are still answered. Incomplete incoming messages
+    /// are abandoned, the controller is asked to detach the node from its neighbors and a
+    /// `ShutdownSummary` is sent as the node's last event.
+    fn shutdown(&mut self) {
+        let ticker = tick(TIMEOUT_CHECK_INTERVAL);
+        let timeout = after(self.shutdown_timeout());
+        let mut commands = self.controller_recv().clone();
+        let mut packets = self.packet_recv().clone();
+        let pending = self.routing_handler().get_active_sessions().len();
+        let failed_before = self.routing_handler().get_failed_sessions();
+
+        let mut forced = false;
+        while self.routing_handler().has_active_sessions() && !forced {
+            select_biased! {
+                recv(commands) -> cmd => match cmd {
+                    Ok(cmd) => {
+                        forced = matches!(
+                            cmd.into_any().downcast_ref::<NodeCommand>(),
+                            Some(NodeCommand::Shutdown)
+                        );
+                    }
+                    Err(_) => commands = never(),
+                },
+
+                recv(packets) -> pkt => match pkt {
+                    Ok(pkt) => {
+                        let router = self.routing_handler();
+                        let from = pkt.routing_header.hops.first().copied().unwrap_or_default();
+                        let _ = match pkt.pack_type {
+                            PacketType::Ack(ack) => router.handle_ack(&ack, pkt.session_id, from),
+                            PacketType::Nack(nack) => {
+                                router.handle_nack(&nack, pkt.session_id, from)
+                            }
+                            PacketType::FloodResponse(flood_response) => {
+                                router.handle_flood_response(&flood_response)
+                            }
+                            PacketType::FloodRequest(flood_request) => {
+                                router.handle_flood_request(flood_request, pkt.session_id)
+                            }
+                            PacketType::MsgFragment(fragment) => {
+                                let mut shr = pkt.routing_header.clone();
+                                shr.reverse();
+                                let idx = fragment.fragment_index;
+                                router.send_nack(shr, pkt.session_id, idx, NackType::Dropped)
+                            }
+                        };
+                    }
+                    Err(_) => packets = never(),
+                },
+
+                recv(ticker) -> now => {
+                    if let Ok(now) = now {
+                        let _ = self.routing_handler().check_timeouts(now);
+                    }
+                }
+
+                recv(timeout) -> _ => forced = true,
+            }
+        }
+
+        let router = self.routing_handler();
+        let _ = router.fail_active_sessions();
+        let failed =
+            usize::try_from(router.get_failed_sessions() - failed_before).unwrap_or(usize::MAX);
+        self.assembler().abandon_all();
+        let abandoned = self.report_abandoned_messages();
+
+        let router = self.routing_handler();
+        let neighbors = router.disconnect_neighbors();
+        let _ = router.notify_controller(NodeEvent::LeavingNetwork {
+            notification_from: router.get_id(),
+            neighbors,
+        });
+        let _ = router.notify_controller(NodeEvent::ShutdownSummary {
+            notification_from: router.get_id(),
+            flushed_sessions: pending.saturating_sub(failed),
+            failed_sessions: failed,
+            abandoned_messages: abandoned,
+            forced,
+        });
+    }
+
     fn run(&mut self) {
+        let ticker = tick(TIMEOUT_CHECK_INTERVAL);
+        let node_ticker = self.tick_interval().map_or_else(never, tick);
         loop {
             select_biased! {
                 recv(self.controller_recv()) -> cmd => {
                     if let Ok(cmd) = cmd {
                         if self.handle_command(cmd) {
-                            println!("Sto terminando");
+                            self.shutdown();
                             return;
                         }
                     }
@@ -62,11 +273,707 @@ pub trait Processor: Send {
                 recv(self.packet_recv()) -> pkt => {
                     if let Ok(pkt) = pkt {
                         if self.handle_packet(pkt).is_err() {
+                            self.shutdown();
                             return;
                         }
                     }
                 }
+
+                recv(ticker) -> now => {
+                    if let Ok(now) = now {
+                        let _ = self.routing_handler().check_timeouts(now);
+                        let _ = self.routing_handler().refresh_network_view(now);
+                        self.assembler().expire(now);
+                        let _ = self.report_abandoned_messages();
+                        self.report_progress(now);
+                    }
+                }
+
+                recv(node_ticker) -> now => {
+                    if let Ok(now) = now {
+                        self.on_tick(now);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod packet_processor_tests {
+    use super::*;
+    use crate::{assembler::FRAGMENT_SIZE, types::Event};
+    use crossbeam_channel::{Sender, unbounded};
+    use std::collections::HashMap;
+    use std::thread::JoinHandle;
+    use wg_internal::{
+        network::SourceRoutingHeader,
+        packet::{FloodRequest, Fragment, Nack, NodeType},
+    };
+
+    struct TestNode {
+        controller_recv: Receiver<Box<dyn Command>>,
+        packet_recv: Receiver<Packet>,
+        assembler: FragmentAssembler,
+        routing_handler: RoutingHandler,
+        tick_interval: Option<Duration>,
+        shutdown_timeout: Duration,
+        ticks: Sender<Instant>,
+        messages: Vec<Vec<u8>>,
+    }
+
+    impl Processor for TestNode {
+        fn controller_recv(&self) -> &Receiver<Box<dyn Command>> {
+            &self.controller_recv
+        }
+
+        fn packet_recv(&self) -> &Receiver<Packet> {
+            &self.packet_recv
+        }
+
+        fn assembler(&mut self) -> &mut FragmentAssembler {
+            &mut self.assembler
+        }
+
+        fn routing_handler(&mut self) -> &mut RoutingHandler {
+            &mut self.routing_handler
+        }
+
+        fn handle_msg(&mut self, msg: Vec<u8>, _from: NodeId, _session_id: u64) {
+            self.messages.push(msg);
+        }
+
+        fn handle_command(&mut self, cmd: Box<dyn Command>) -> bool {
+            match cmd.into_any().downcast_ref::<NodeCommand>() {
+                Some(NodeCommand::Shutdown) => true,
+                Some(NodeCommand::GetStats) => {
+                    self.report_stats();
+                    false
+                }
+                Some(NodeCommand::GetNetworkView) => {
+                    self.report_network_view();
+                    false
+                }
+                Some(NodeCommand::StartFlood) => {
+                    let _ = self.routing_handler.start_flood();
+                    false
+                }
+                _ => false,
+            }
+        }
+
+        fn tick_interval(&self) -> Option<Duration> {
+            self.tick_interval
+        }
+
+        fn on_tick(&mut self, now: Instant) {
+            let _ = self.ticks.send(now);
+        }
+
+        fn shutdown_timeout(&self) -> Duration {
+            self.shutdown_timeout
+        }
+    }
+
+    struct Setup {
+        node: TestNode,
+        controller_send: Sender<Box<dyn Command>>,
+        packet_send: Sender<Packet>,
+        event_recv: Receiver<Box<dyn Event>>,
+        ticks_recv: Receiver<Instant>,
+    }
+
+    fn create_test_node() -> Setup {
+        let (controller_send, controller_recv) = unbounded::<Box<dyn Command>>();
+        let (packet_send, packet_recv) = unbounded();
+        let (event_send, event_recv) = unbounded::<Box<dyn Event>>();
+        let (ticks_send, ticks_recv) = unbounded();
+        let node = TestNode {
+            controller_recv,
+            packet_recv,
+            assembler: FragmentAssembler::default(),
+            routing_handler: RoutingHandler::new(1, NodeType::Client, HashMap::new(), event_send),
+            tick_interval: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            ticks: ticks_send,
+            messages: Vec::new(),
+        };
+        Setup {
+            node,
+            controller_send,
+            packet_send,
+            event_recv,
+            ticks_recv,
+        }
+    }
+
+    fn spawn(mut node: TestNode) -> JoinHandle<()> {
+        std::thread::spawn(move || node.run())
+    }
+
+    /// Runs a node with the given tick interval for `duration` and returns the ticks it saw
+    fn run_for(tick_interval: Option<Duration>, duration: Duration) -> Vec<Instant> {
+        let mut setup = create_test_node();
+        setup.node.tick_interval = tick_interval;
+
+        let handle = spawn(setup.node);
+        std::thread::sleep(duration);
+        setup
+            .controller_send
+            .send(Box::new(NodeCommand::Shutdown))
+            .unwrap();
+        handle.join().unwrap();
+        setup.ticks_recv.try_iter().collect()
+    }
+
+    /// Packet carrying fragment `index` of `total`, sent by node 2 to node 1
+    fn fragment_packet(data: &[u8], index: u64, total: u64, session_id: u64) -> Packet {
+        let mut arr = [0u8; FRAGMENT_SIZE];
+        arr[..data.len()].copy_from_slice(data);
+        let mut fragment = Fragment::new(index, total, arr);
+        fragment.length = u8::try_from(data.len()).unwrap();
+        Packet::new_fragment(
+            SourceRoutingHeader::new(vec![2, 1], 1),
+            session_id,
+            fragment,
+        )
+    }
+
+    fn node_events(event_recv: &Receiver<Box<dyn Event>>) -> Vec<NodeEvent> {
+        event_recv
+            .try_iter()
+            .filter_map(|e| e.into_any().downcast::<NodeEvent>().ok())
+            .map(|e| *e)
+            .collect()
+    }
+
+    #[test]
+    /// Tests that `run` calls `on_tick` at the configured interval
+    fn test_on_tick_called() {
+        let ticks = run_for(Some(Duration::from_millis(10)), Duration::from_millis(200));
+        assert!(ticks.len() >= 5, "only {} ticks", ticks.len());
+        assert!(ticks.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    /// Tests that `on_tick` is never called when no interval is configured
+    fn test_on_tick_disabled_by_default() {
+        let ticks = run_for(None, Duration::from_millis(150));
+        assert!(ticks.is_empty());
+    }
+
+    #[test]
+    /// Tests that a shutting down node waits for its outgoing session to be acknowledged,
+    /// then detaches from its neighbors and reports a summary
+    fn test_graceful_shutdown_flushes_sessions() {
+        let mut setup = create_test_node();
+        let (neighbor_send, neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+        setup
+            .node
+            .routing_handler
+            .send_message(b"bye", 2, None)
+            .unwrap();
+
+        let handle = spawn(setup.node);
+        setup
+            .controller_send
+            .send(Box::new(NodeCommand::Shutdown))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+
+        let fragment = neighbor_recv.recv().unwrap();
+        let mut ack = Packet::new_ack(fragment.routing_header.clone(), fragment.session_id, 0);
+        ack.routing_header.hops.reverse();
+        ack.routing_header.hop_index = 1;
+        setup.packet_send.send(ack).unwrap();
+        handle.join().unwrap();
+
+        let events = node_events(&setup.event_recv);
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, NodeEvent::SessionFailed { .. }))
+        );
+        assert!(events.iter().any(|e| matches!(
+            e,
+            NodeEvent::LeavingNetwork { notification_from: 1, neighbors } if neighbors == &vec![2]
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(NodeEvent::ShutdownSummary {
+                notification_from: 1,
+                flushed_sessions: 1,
+                failed_sessions: 0,
+                abandoned_messages: 0,
+                forced: false,
+            })
+        ));
+    }
+
+    #[test]
+    /// Tests that sessions still unacknowledged after the shutdown timeout are failed
+    fn test_shutdown_timeout_forces_termination() {
+        let mut setup = create_test_node();
+        setup.node.shutdown_timeout = Duration::from_millis(100);
+        let (neighbor_send, _neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+        setup
+            .node
+            .routing_handler
+            .send_message(b"bye", 2, None)
+            .unwrap();
+
+        let handle = spawn(setup.node);
+        setup
+            .controller_send
+            .send(Box::new(NodeCommand::Shutdown))
+            .unwrap();
+        handle.join().unwrap();
+
+        let events = node_events(&setup.event_recv);
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, NodeEvent::SessionFailed { to: 2, .. }))
+        );
+        assert!(matches!(
+            events.last(),
+            Some(NodeEvent::ShutdownSummary {
+                flushed_sessions: 0,
+                failed_sessions: 1,
+                forced: true,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    /// Tests that a node stopping because a packet cannot be handled still leaves the network
+    /// and reports a summary
+    fn test_packet_error_reports_summary() {
+        let setup = create_test_node();
+        let handle = spawn(setup.node);
+        // without neighbors the fragment cannot be acknowledged
+        setup
+            .packet_send
+            .send(fragment_packet(b"hello", 0, 1, 7))
+            .unwrap();
+        handle.join().unwrap();
+
+        let events = node_events(&setup.event_recv);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            NodeEvent::LeavingNetwork { notification_from: 1, neighbors } if neighbors.is_empty()
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(NodeEvent::ShutdownSummary {
+                notification_from: 1,
+                forced: false,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    /// Tests that a second `Shutdown` command terminates the node right away
+    fn test_second_shutdown_forces_termination() {
+        let mut setup = create_test_node();
+        let (neighbor_send, _neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+        setup
+            .node
+            .routing_handler
+            .send_message(b"bye", 2, None)
+            .unwrap();
+
+        let started = Instant::now();
+        let handle = spawn(setup.node);
+        setup
+            .controller_send
+            .send(Box::new(NodeCommand::Shutdown))
+            .unwrap();
+        setup
+            .controller_send
+            .send(Box::new(NodeCommand::Shutdown))
+            .unwrap();
+        handle.join().unwrap();
+        assert!(started.elapsed() < DEFAULT_SHUTDOWN_TIMEOUT);
+
+        let events = node_events(&setup.event_recv);
+        assert!(matches!(
+            events.last(),
+            Some(NodeEvent::ShutdownSummary {
+                failed_sessions: 1,
+                forced: true,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    /// Tests that received fragments and messages are counted and reported on `GetStats`
+    fn test_get_stats() {
+        let setup = create_test_node();
+        let (neighbor_send, _neighbor_recv) = unbounded();
+        let mut node = setup.node;
+        node.routing_handler.add_neighbor(2, neighbor_send);
+
+        let message = Envelope::new(Codec::Json, b"hi".to_vec()).to_bytes();
+        let mut data = [0u8; FRAGMENT_SIZE];
+        data[..message.len()].copy_from_slice(&message);
+        let mut fragment = Fragment::new(0, 1, data);
+        fragment.length = u8::try_from(message.len()).unwrap();
+        let header = SourceRoutingHeader::new(vec![2, 1], 1);
+        node.handle_packet(Packet::new_fragment(header, 9, fragment))
+            .unwrap();
+
+        assert!(!node.handle_command(Box::new(NodeCommand::GetStats)));
+        let stats = node_events(&setup.event_recv)
+            .into_iter()
+            .find_map(|e| match e {
+                NodeEvent::Stats {
+                    notification_from: 1,
+                    stats,
+                } => Some(stats),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(stats.fragments_received, 1);
+        assert_eq!(stats.bytes_received, message.len() as u64);
+        assert_eq!(stats.messages_received, 1);
+        assert_eq!(stats.acks_sent, 1);
+    }
+
+    #[test]
+    /// Tests that `GetNetworkView` reports the topology learned by the node
+    /// and that `StartFlood` floods every neighbor
+    fn test_network_view_and_start_flood() {
+        let mut setup = create_test_node();
+        let (neighbor_send, neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+
+        assert!(
+            !setup
+                .node
+                .handle_command(Box::new(NodeCommand::GetNetworkView))
+        );
+        let network = node_events(&setup.event_recv)
+            .into_iter()
+            .find_map(|e| match e {
+                NodeEvent::NetworkView {
+                    notification_from: 1,
+                    network,
+                } => Some(network),
+                _ => None,
+            })
+            .unwrap();
+        let root = network.get_node(1).unwrap();
+        assert_eq!(root.get_node_type(), NodeType::Client);
+        assert_eq!(root.get_adjacents(), &vec![2]);
+
+        assert!(!setup.node.handle_command(Box::new(NodeCommand::StartFlood)));
+        let packet = neighbor_recv.try_recv().unwrap();
+        assert!(matches!(packet.pack_type, PacketType::FloodRequest(_)));
+        assert!(
+            node_events(&setup.event_recv)
+                .iter()
+                .any(|e| matches!(e, NodeEvent::FloodStarted(_, 1)))
+        );
+    }
+
+    #[test]
+    /// Tests that `MessageAbandoned` is sent when the assembler evicts a partial message
+    /// to make room for a new one
+    fn test_abandoned_message_reported_on_limit() {
+        let mut setup = create_test_node();
+        let (neighbor_send, _neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+        setup
+            .node
+            .assembler
+            .set_limits(1, FRAGMENT_SIZE * 4, Duration::from_secs(60));
+
+        setup
+            .node
+            .handle_packet(fragment_packet(&[1; FRAGMENT_SIZE], 0, 2, 7))
+            .unwrap();
+        assert!(
+            !node_events(&setup.event_recv)
+                .iter()
+                .any(|e| matches!(e, NodeEvent::MessageAbandoned { .. }))
+        );
+
+        setup
+            .node
+            .handle_packet(fragment_packet(&[2; FRAGMENT_SIZE], 0, 2, 8))
+            .unwrap();
+        let abandoned = node_events(&setup.event_recv)
+            .into_iter()
+            .filter(|e| matches!(e, NodeEvent::MessageAbandoned { .. }))
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            abandoned.as_slice(),
+            [NodeEvent::MessageAbandoned {
+                notification_from: 1,
+                from: 2,
+                session_id: 7,
+                received_fragments: 1,
+                total_fragments: 2,
+            }]
+        ));
+    }
+
+    #[test]
+    /// Tests that `run` reports the partial messages the assembler drops after its idle timeout
+    fn test_abandoned_message_reported_on_timeout() {
+        let mut setup = create_test_node();
+        let (neighbor_send, _neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+        setup
+            .node
+            .assembler
+            .set_limits(1, FRAGMENT_SIZE * 4, Duration::from_millis(20));
+
+        let handle = spawn(setup.node);
+        setup
+            .packet_send
+            .send(fragment_packet(&[1; FRAGMENT_SIZE], 0, 2, 7))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+        setup
+            .controller_send
+            .send(Box::new(NodeCommand::Shutdown))
+            .unwrap();
+        handle.join().unwrap();
+
+        let events = node_events(&setup.event_recv);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            NodeEvent::MessageAbandoned {
+                from: 2,
+                session_id: 7,
+                received_fragments: 1,
+                ..
+            }
+        )));
+        // it was dropped before the shutdown
+        assert!(matches!(
+            events.last(),
+            Some(NodeEvent::ShutdownSummary {
+                abandoned_messages: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    /// Tests that `ReassemblyProgress` is sent for a partial message, at most once per interval
+    fn test_reassembly_progress_throttled() {
+        let mut setup = create_test_node();
+        let (neighbor_send, _neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+        let progress_events = |event_recv: &Receiver<Box<dyn Event>>| {
+            node_events(event_recv)
+                .into_iter()
+                .filter_map(|e| match e {
+                    NodeEvent::ReassemblyProgress {
+                        notification_from: 1,
+                        from: 2,
+                        session_id: 7,
+                        received_fragments,
+                        total_fragments: 3,
+                        received_bytes,
+                    } => Some((received_fragments, received_bytes)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let now = Instant::now();
+        setup
+            .node
+            .handle_packet(fragment_packet(&[1; FRAGMENT_SIZE], 0, 3, 7))
+            .unwrap();
+        setup.node.report_progress(now);
+        assert_eq!(progress_events(&setup.event_recv), vec![(1, FRAGMENT_SIZE)]);
+
+        setup
+            .node
+            .handle_packet(fragment_packet(&[1; FRAGMENT_SIZE], 1, 3, 7))
+            .unwrap();
+        setup
+            .node
+            .report_progress(now + PROGRESS_REPORT_INTERVAL / 2);
+        assert!(progress_events(&setup.event_recv).is_empty());
+
+        setup.node.report_progress(now + PROGRESS_REPORT_INTERVAL);
+        assert_eq!(
+            progress_events(&setup.event_recv),
+            vec![(2, 2 * FRAGMENT_SIZE)]
+        );
+
+        // nothing new arrived
+        setup
+            .node
+            .report_progress(now + PROGRESS_REPORT_INTERVAL * 3);
+        assert!(progress_events(&setup.event_recv).is_empty());
+    }
+
+    #[test]
+    /// Tests that a message whose envelope fails its checksum is reported as corrupted
+    /// and never handed to `handle_msg`
+    fn test_corrupted_message_reported() {
+        let mut setup = create_test_node();
+        let (neighbor_send, _neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+
+        let message = Envelope::new(Codec::Json, vec![b'a'; 200]).to_bytes();
+        let mut corrupted = message.clone();
+        corrupted[FRAGMENT_SIZE + 10] ^= 0x01;
+        for (session_id, bytes) in [(7, &corrupted), (8, &message)] {
+            let chunks = bytes.chunks(FRAGMENT_SIZE).collect::<Vec<_>>();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let packet = fragment_packet(chunk, i as u64, chunks.len() as u64, session_id);
+                setup.node.handle_packet(packet).unwrap();
             }
         }
+
+        // only the intact message is delivered
+        assert_eq!(setup.node.messages, vec![vec![b'a'; 200]]);
+        let corrupted = node_events(&setup.event_recv)
+            .into_iter()
+            .filter_map(|e| match e {
+                NodeEvent::MessageCorrupted {
+                    notification_from: 1,
+                    from: 2,
+                    session_id,
+                    error,
+                } => Some((session_id, error)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            corrupted,
+            vec![(7, EnvelopeError::ChecksumMismatch.to_string())]
+        );
+        assert_eq!(setup.node.routing_handler.get_stats().messages_received, 1);
+    }
+
+    #[test]
+    /// Tests that messages sent as raw JSON, without an envelope, are still handled
+    /// while other payloads without an envelope are reported as corrupted
+    fn test_legacy_json_message_accepted() {
+        let mut setup = create_test_node();
+        let (neighbor_send, _neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+
+        let legacy = br#"{"Chat":{"text":"hi"}}"#;
+        setup
+            .node
+            .handle_packet(fragment_packet(legacy, 0, 1, 7))
+            .unwrap();
+        setup
+            .node
+            .handle_packet(fragment_packet(b"1", 0, 1, 8))
+            .unwrap();
+        setup
+            .node
+            .handle_packet(fragment_packet(b"{not json at all", 0, 1, 9))
+            .unwrap();
+
+        assert_eq!(setup.node.messages, vec![legacy.to_vec(), b"1".to_vec()]);
+        let corrupted = node_events(&setup.event_recv)
+            .into_iter()
+            .filter_map(|e| match e {
+                NodeEvent::MessageCorrupted {
+                    session_id, error, ..
+                } => Some((session_id, error)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            corrupted,
+            vec![(9, EnvelopeError::UnsupportedVersion(b'{').to_string())]
+        );
+        assert_eq!(setup.node.routing_handler.get_stats().messages_received, 2);
+    }
+
+    #[test]
+    /// Tests that an Ack whose window refill cannot be sent does not make `handle_packet` fail
+    fn test_failed_window_refill_not_fatal() {
+        let mut setup = create_test_node();
+        let (neighbor_send, neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+        setup.node.routing_handler.set_window_policy(1, 1);
+        setup
+            .node
+            .routing_handler
+            .send_message(&[0; FRAGMENT_SIZE + 1], 2, None)
+            .unwrap();
+        let fragment = neighbor_recv.recv().unwrap();
+        setup.node.routing_handler.remove_neighbor(2);
+
+        let mut ack = Packet::new_ack(fragment.routing_header.clone(), fragment.session_id, 0);
+        ack.routing_header.hops.reverse();
+        assert!(setup.node.handle_packet(ack).is_ok());
+        assert!(neighbor_recv.try_recv().is_err());
+    }
+
+    #[test]
+    /// Tests that while draining, fragments are refused with a Nack and floods are answered
+    fn test_shutdown_drain_refuses_fragments() {
+        let mut setup = create_test_node();
+        let (neighbor_send, neighbor_recv) = unbounded();
+        setup.node.routing_handler.add_neighbor(2, neighbor_send);
+        setup
+            .node
+            .routing_handler
+            .send_message(b"bye", 2, None)
+            .unwrap();
+        let fragment = neighbor_recv.recv().unwrap();
+
+        let handle = spawn(setup.node);
+        setup
+            .controller_send
+            .send(Box::new(NodeCommand::Shutdown))
+            .unwrap();
+        setup
+            .packet_send
+            .send(fragment_packet(b"hello", 0, 1, 7))
+            .unwrap();
+        let flood_request = FloodRequest {
+            flood_id: 1,
+            initiator_id: 2,
+            path_trace: vec![(2, NodeType::Drone)],
+        };
+        setup
+            .packet_send
+            .send(Packet::new_flood_request(
+                SourceRoutingHeader::empty_route(),
+                9,
+                flood_request,
+            ))
+            .unwrap();
+
+        let nack = neighbor_recv.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(nack.session_id, 7);
+        assert_eq!(nack.routing_header.hops, vec![1, 2]);
+        assert!(matches!(
+            nack.pack_type,
+            PacketType::Nack(Nack {
+                fragment_index: 0,
+                nack_type: NackType::Dropped
+            })
+        ));
+        let response = neighbor_recv.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(matches!(response.pack_type, PacketType::FloodResponse(_)));
+
+        let mut ack = Packet::new_ack(fragment.routing_header.clone(), fragment.session_id, 0);
+        ack.routing_header.hops.reverse();
+        setup.packet_send.send(ack).unwrap();
+        handle.join().unwrap();
     }
 }
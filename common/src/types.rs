@@ -1,3 +1,6 @@
+use crate::envelope::{Codec, EnvelopeError};
+use crate::network::Network;
+use crate::routing_handler::TrafficStats;
 use anyhow::anyhow;
 use crossbeam_channel::Sender;
 use serde::{Deserialize, Serialize};
@@ -153,28 +156,58 @@ pub enum WebRequest {
     TextFilesListQuery,
 
     #[serde(rename = "file?")]
-    FileQuery { file_id: String },
+    FileQuery {
+        file_id: String,
+        // ids of the codecs the requester can decode, the response falls back to JSON without them
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        accepted_codecs: Vec<u8>,
+    },
 
     #[serde(rename = "media?")]
-    MediaQuery { media_id: String },
+    MediaQuery {
+        media_id: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        accepted_codecs: Vec<u8>,
+    },
 }
 
 impl WebRequest {
     #[must_use]
     pub fn get_file_id(&self) -> Option<String> {
         match self {
-            Self::FileQuery { file_id } => Some(file_id.clone()),
-            Self::MediaQuery { media_id } => Some(media_id.clone()),
+            Self::FileQuery { file_id, .. } => Some(file_id.clone()),
+            Self::MediaQuery { media_id, .. } => Some(media_id.clone()),
             _ => None,
         }
     }
+
+    /// Ids of the codecs the requester accepts for the response
+    #[must_use]
+    pub fn get_accepted_codecs(&self) -> &[u8] {
+        match self {
+            Self::FileQuery {
+                accepted_codecs, ..
+            }
+            | Self::MediaQuery {
+                accepted_codecs, ..
+            } => accepted_codecs,
+            _ => &[],
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "response_type")]
 pub enum WebResponse {
     #[serde(rename = "server_type!")]
-    ServerType { server_type: ServerType },
+    ServerType {
+        server_type: ServerType,
+        // missing in responses from peers which predate versioning
+        #[serde(default)]
+        protocol_version: u16,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+    },
 
     #[serde(rename = "files_list!")]
     TextFilesList { files: Vec<String> },
@@ -192,6 +225,91 @@ pub enum WebResponse {
     BadUuid(String),
 }
 
+impl WebResponse {
+    /// Serializes the response with the given codec
+    /// # Errors
+    /// Returns an error if serialization fails.
+    pub fn encode(self, codec: Codec) -> Result<Vec<u8>, EnvelopeError> {
+        match codec {
+            Codec::Json => codec.encode(&self),
+            _ => codec.encode(&CompactWebResponse::from(self)),
+        }
+    }
+
+    /// Deserializes a response encoded with the given codec
+    /// # Errors
+    /// Returns an error if the bytes are not a valid response.
+    pub fn decode(bytes: &[u8], codec: Codec) -> Result<Self, EnvelopeError> {
+        match codec {
+            Codec::Json => codec.decode(bytes),
+            _ => codec.decode::<CompactWebResponse>(bytes).map(Self::from),
+        }
+    }
+}
+
+/// Externally tagged twin of `WebResponse`: binary codecs can't decode internally tagged enums
+#[derive(Serialize, Deserialize)]
+enum CompactWebResponse {
+    ServerType {
+        server_type: ServerType,
+        protocol_version: u16,
+        capabilities: Vec<Capability>,
+    },
+    TextFilesList {
+        files: Vec<String>,
+    },
+    TextFile {
+        file_data: Vec<u8>,
+    },
+    MediaFile {
+        media_data: Vec<u8>,
+    },
+    ErrorFileNotFound(Uuid),
+    BadUuid(String),
+}
+
+impl From<WebResponse> for CompactWebResponse {
+    fn from(value: WebResponse) -> Self {
+        match value {
+            WebResponse::ServerType {
+                server_type,
+                protocol_version,
+                capabilities,
+            } => Self::ServerType {
+                server_type,
+                protocol_version,
+                capabilities,
+            },
+            WebResponse::TextFilesList { files } => Self::TextFilesList { files },
+            WebResponse::TextFile { file_data } => Self::TextFile { file_data },
+            WebResponse::MediaFile { media_data } => Self::MediaFile { media_data },
+            WebResponse::ErrorFileNotFound(uuid) => Self::ErrorFileNotFound(uuid),
+            WebResponse::BadUuid(uuid) => Self::BadUuid(uuid),
+        }
+    }
+}
+
+impl From<CompactWebResponse> for WebResponse {
+    fn from(value: CompactWebResponse) -> Self {
+        match value {
+            CompactWebResponse::ServerType {
+                server_type,
+                protocol_version,
+                capabilities,
+            } => Self::ServerType {
+                server_type,
+                protocol_version,
+                capabilities,
+            },
+            CompactWebResponse::TextFilesList { files } => Self::TextFilesList { files },
+            CompactWebResponse::TextFile { file_data } => Self::TextFile { file_data },
+            CompactWebResponse::MediaFile { media_data } => Self::MediaFile { media_data },
+            CompactWebResponse::ErrorFileNotFound(uuid) => Self::ErrorFileNotFound(uuid),
+            CompactWebResponse::BadUuid(uuid) => Self::BadUuid(uuid),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(tag = "request_type")]
 pub enum ChatRequest {
@@ -212,7 +330,13 @@ pub enum ChatRequest {
 #[serde(tag = "response_type")]
 pub enum ChatResponse {
     #[serde(rename = "server_type!")]
-    ServerType { server_type: ServerType },
+    ServerType {
+        server_type: ServerType,
+        #[serde(default)]
+        protocol_version: u16,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+    },
 
     #[serde(rename = "client_list!")]
     ClientList { list_of_client_ids: Vec<NodeId> },
@@ -422,6 +546,56 @@ pub enum NodeEvent {
         notification_from: NodeId,
         from: NodeId,
     }, // server_id, requester_id
+    SessionFailed {
+        notification_from: NodeId,
+        session_id: u64,
+        to: NodeId,
+    }, // fragments of the session could not be delivered after all retries
+    UnexpectedRecipient {
+        notification_from: NodeId,
+        node: NodeId,
+        session_id: u64,
+    }, // node received a fragment not meant for it, its view has been repaired
+    MessageAbandoned {
+        notification_from: NodeId,
+        from: NodeId,
+        session_id: u64,
+        received_fragments: u64,
+        total_fragments: u64,
+    }, // incomplete message dropped by the assembler, expired or evicted to bound memory
+    ReassemblyProgress {
+        notification_from: NodeId,
+        from: NodeId,
+        session_id: u64,
+        received_fragments: u64,
+        total_fragments: u64,
+        received_bytes: usize,
+    }, // fragments of an incoming message received so far
+    MessageCorrupted {
+        notification_from: NodeId,
+        from: NodeId,
+        session_id: u64,
+        error: String,
+    }, // reassembled message whose envelope could not be verified, it was discarded
+    LeavingNetwork {
+        notification_from: NodeId,
+        neighbors: Vec<NodeId>,
+    }, // node is shutting down, its sender should be removed from these neighbors
+    ShutdownSummary {
+        notification_from: NodeId,
+        flushed_sessions: usize,
+        failed_sessions: usize,
+        abandoned_messages: usize,
+        forced: bool,
+    }, // last event sent by a node before its thread exits
+    Stats {
+        notification_from: NodeId,
+        stats: TrafficStats,
+    }, // answer to NodeCommand::GetStats
+    NetworkView {
+        notification_from: NodeId,
+        network: Network,
+    }, // answer to NodeCommand::GetNetworkView, the topology learned by the node
 }
 
 #[derive(Debug, Clone)]
@@ -429,6 +603,9 @@ pub enum NodeCommand {
     AddSender(NodeId, Sender<Packet>),
     RemoveSender(NodeId),
     Shutdown,
+    GetStats,
+    GetNetworkView,
+    StartFlood,
 }
 
 impl NodeCommand {
@@ -459,6 +636,52 @@ pub enum ServerType {
     MediaServer,
 }
 
+/// Version of the application protocol spoken by these nodes, advertised in `ServerType` responses.
+/// Peers which do not advertise one are seen as version 0.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Optional features a server can advertise in its `ServerType` response
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// File responses in the compact codecs listed in `accepted_codecs`
+    Compression,
+    Search,
+    Uploads,
+    Rooms,
+    /// Advertised by a newer peer and not understood by this node
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a server advertised about itself in its `ServerType` response
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub server_type: ServerType,
+    pub protocol_version: u16,
+    pub capabilities: Vec<Capability>,
+}
+
+impl ServerInfo {
+    #[must_use]
+    pub fn new(
+        server_type: ServerType,
+        protocol_version: u16,
+        capabilities: Vec<Capability>,
+    ) -> Self {
+        Self {
+            server_type,
+            protocol_version,
+            capabilities,
+        }
+    }
+
+    #[must_use]
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum NodeType {
     ChatServer,
@@ -141,7 +141,7 @@ mod tests {
         assert_eq!(drones.len(), 2, "Drones should be 2");
         assert_eq!(clients.len(), 1, "Client should be 1");
         assert_eq!(servers.len(), 1, "Server should be 1");
-        assert_eq!(network.nodes.len(), 4, "Nodes should be 4");
+        assert_eq!(network.len(), 4, "Nodes should be 4");
         assert_eq!(
             clients.get(&1).unwrap().0,
             NodeType::WebBrowser,
@@ -153,7 +153,7 @@ mod tests {
             "Server should be a TextServer"
         );
         assert_eq!(
-            network.nodes.iter().find(|n| n.id == 1).unwrap().get_adjacents(),
+            network.get_node(1).unwrap().get_adjacents(),
             &running_sim
                 .config
                 .client
@@ -164,17 +164,17 @@ mod tests {
             "Adjacents of client 1 are not the expected"
         );
         assert_eq!(
-            network.nodes.iter().find(|n| n.id == 2).unwrap().get_adjacents(),
+            network.get_node(2).unwrap().get_adjacents(),
             &running_sim.config.drone.iter().find(|c| c.id == 2).unwrap().connected_node_ids,
             "Adjacents of drone 2 are not the expected"
         );
         assert_eq!(
-            network.nodes.iter().find(|n| n.id == 3).unwrap().get_adjacents(),
+            network.get_node(3).unwrap().get_adjacents(),
             &running_sim.config.drone.iter().find(|c| c.id == 3).unwrap().connected_node_ids,
             "Adjacents of drone 3 are not the expected"
         );
         assert_eq!(
-            network.nodes.iter().find(|n| n.id == 4).unwrap().get_adjacents(),
+            network.get_node(4).unwrap().get_adjacents(),
             &running_sim
                 .config
                 .server
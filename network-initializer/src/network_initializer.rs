@@ -7,8 +7,8 @@ use client::chat_client::ChatClient;
 use client::web_browser::WebBrowser;
 use common::Processor;
 use common::network::Network;
-use common::types::{Command, Event, NodeType as CommonNodeType};
-use crossbeam::channel::{Receiver, Sender};
+use common::types::{Command, Event, NodeCommand, NodeEvent, NodeType as CommonNodeType};
+use crossbeam::channel::{Receiver, Sender, select, unbounded};
 use server::{ChatServer, MediaServer, TextServer};
 use std::collections::HashMap;
 use std::thread::JoinHandle;
@@ -33,6 +33,8 @@ pub struct NetworkInitializer<State = Uninitialized> {
     drone_event_channel: Channel<DroneEvent>,
     // controller receives events from nodes
     node_event_channel: Channel<Box<dyn Event>>,
+    // node events forwarded to the controller by the relay
+    controller_event_channel: Channel<Box<dyn Event>>,
     total_nodes: usize,
     pub(crate) config: Config,
     // do not exists
@@ -47,6 +49,8 @@ pub struct NetworkInitializer<State = Uninitialized> {
 
     // to keep track of threads and join them at the end
     node_handles: Vec<JoinHandle<()>>,
+    // stop signal and thread of the node event relay
+    event_relay: Option<(Sender<()>, JoinHandle<()>)>,
 }
 
 impl NetworkInitializer<Uninitialized> {
@@ -62,6 +66,7 @@ impl NetworkInitializer<Uninitialized> {
             node_command_channels: HashMap::new(),
             drone_event_channel: Channel::new(),
             node_event_channel: Channel::new(),
+            controller_event_channel: Channel::new(),
             total_nodes: config.drone.len() + config.client.len() + config.server.len(),
             config,
             // do not exists
@@ -71,6 +76,7 @@ impl NetworkInitializer<Uninitialized> {
             initialized_servers: Vec::new(),
             initialized_drones: Vec::new(),
             node_handles: Vec::new(),
+            event_relay: None,
         }
     }
 
@@ -241,6 +247,7 @@ impl NetworkInitializer<Initialized> {
             node_command_channels: initializer.node_command_channels,
             drone_event_channel: initializer.drone_event_channel,
             node_event_channel: initializer.node_event_channel,
+            controller_event_channel: initializer.controller_event_channel,
             total_nodes: initializer.total_nodes,
             config: initializer.config,
             state: std::marker::PhantomData,
@@ -249,6 +256,7 @@ impl NetworkInitializer<Initialized> {
             initialized_servers: initializer.initialized_servers,
             initialized_drones: initializer.initialized_drones,
             node_handles: Vec::new(),
+            event_relay: None,
         }
     }
 
@@ -272,8 +280,26 @@ impl NetworkInitializer<Initialized> {
             });
             self.node_handles.push(handle);
         }
+        self.start_event_relay();
         NetworkInitializer::<Running>::new(self)
     }
+
+    fn start_event_relay(&mut self) {
+        let (stop_sender, stop_receiver) = unbounded();
+        let relay = NodeEventRelay {
+            events: self.node_event_channel.get_receiver(),
+            controller: self.controller_event_channel.get_sender(),
+            stop: stop_receiver,
+            drone_command_channels: self.drone_command_channels.clone(),
+            node_command_channels: self
+                .node_command_channels
+                .iter()
+                .map(|(id, (_, channel))| (*id, channel.clone()))
+                .collect(),
+        };
+        let handle = std::thread::spawn(move || relay.run());
+        self.event_relay = Some((stop_sender, handle));
+    }
 }
 
 impl NetworkInitializer<Running> {
@@ -305,6 +331,7 @@ impl NetworkInitializer<Running> {
             node_command_channels: initializer.node_command_channels,
             drone_event_channel: initializer.drone_event_channel,
             node_event_channel: initializer.node_event_channel,
+            controller_event_channel: initializer.controller_event_channel,
             total_nodes: initializer.total_nodes,
             config: initializer.config,
             state: std::marker::PhantomData,
@@ -313,6 +340,7 @@ impl NetworkInitializer<Running> {
             initialized_servers: initializer.initialized_servers,
             initialized_drones: initializer.initialized_drones,
             node_handles: initializer.node_handles,
+            event_relay: initializer.event_relay,
         }
     }
 
@@ -341,11 +369,37 @@ impl NetworkInitializer<Running> {
                 }
             }
         }
+
+        if let Some((stop, handle)) = self.event_relay.take() {
+            let _ = stop.send(());
+            let _ = handle.join();
+        }
     }
 
+    /// Updates the controller's bookkeeping after an event read from `get_nodes_event_receiver`:
+    /// a node which sent `LeavingNetwork` is forgotten. Its neighbors were already told to drop
+    /// its sender by the event relay, before the event was forwarded.
+    pub fn handle_node_event(&mut self, event: &dyn Event) {
+        if let Some(NodeEvent::LeavingNetwork {
+            notification_from, ..
+        }) = event.as_any().downcast_ref::<NodeEvent>()
+        {
+            self.forget_node(*notification_from);
+        }
+    }
+
+    fn forget_node(&mut self, node_id: NodeId) {
+        self.node_command_channels.remove(&node_id);
+        self.communications_channels.remove(&node_id);
+        if let Some(network) = self.network_view.as_mut() {
+            network.remove_node(node_id);
+        }
+    }
+
+    /// Events sent by the nodes, forwarded by the event relay once it has reacted to them
     #[must_use]
     pub fn get_nodes_event_receiver(&self) -> Receiver<Box<dyn Event>> {
-        self.node_event_channel.get_receiver()
+        self.controller_event_channel.get_receiver()
     }
 
     #[must_use]
@@ -398,3 +452,114 @@ impl NetworkInitializer<Running> {
         &self.communications_channels
     }
 }
+
+/// Forwards the node events to the controller. A node which leaves the network is first
+/// detached from its neighbors, so they stop routing through it even if nobody reads the events.
+struct NodeEventRelay {
+    events: Receiver<Box<dyn Event>>,
+    controller: Sender<Box<dyn Event>>,
+    stop: Receiver<()>,
+    drone_command_channels: HashMap<NodeId, Sender<DroneCommand>>,
+    node_command_channels: HashMap<NodeId, Sender<Box<dyn Command>>>,
+}
+
+impl NodeEventRelay {
+    fn run(self) {
+        loop {
+            select! {
+                recv(self.events) -> event => match event {
+                    Ok(event) => self.relay(event),
+                    Err(_) => return,
+                },
+                recv(self.stop) -> _ => {
+                    // forward what the nodes sent while stopping
+                    for event in self.events.try_iter() {
+                        self.relay(event);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    fn relay(&self, event: Box<dyn Event>) {
+        if let Some(NodeEvent::LeavingNetwork {
+            notification_from,
+            neighbors,
+        }) = (*event).as_any().downcast_ref::<NodeEvent>()
+        {
+            for neighbor in neighbors {
+                if let Some(channel) = self.drone_command_channels.get(neighbor) {
+                    let _ = channel.send(DroneCommand::RemoveSender(*notification_from));
+                } else if let Some(channel) = self.node_command_channels.get(neighbor) {
+                    let _ = channel.send(Box::new(NodeCommand::RemoveSender(*notification_from)));
+                }
+            }
+        }
+        let _ = self.controller.send(event);
+    }
+}
+
+#[cfg(test)]
+mod network_initializer_tests {
+    use super::*;
+    use crossbeam::channel::unbounded;
+    use std::time::Duration;
+
+    #[test]
+    /// Tests that the drones next to a node which shuts down are told to drop its sender
+    fn test_leaving_node_detached() {
+        let mut initialized =
+            NetworkInitializer::<Uninitialized>::new("./tests/correct_config.toml").initialize();
+
+        // client 4 is connected to drones 2 and 3, watch their commands instead of running them
+        let (send_2, recv_2) = unbounded();
+        let (send_3, recv_3) = unbounded();
+        let drone_2 = initialized
+            .drone_command_channels
+            .insert(2, send_2)
+            .unwrap();
+        let drone_3 = initialized
+            .drone_command_channels
+            .insert(3, send_3)
+            .unwrap();
+        let mut running = initialized.start_simulation();
+        let events = running.get_nodes_event_receiver();
+
+        running.get_clients()[&4]
+            .1
+            .send(Box::new(NodeCommand::Shutdown))
+            .unwrap();
+        let leaving = loop {
+            let event = events
+                .recv_timeout(Duration::from_secs(10))
+                .expect("client 4 did not leave");
+            if matches!(
+                (*event).as_any().downcast_ref::<NodeEvent>(),
+                Some(NodeEvent::LeavingNetwork {
+                    notification_from: 4,
+                    ..
+                })
+            ) {
+                break event;
+            }
+        };
+        // the relay detaches the node before forwarding the event
+        assert!(matches!(
+            recv_2.try_recv(),
+            Ok(DroneCommand::RemoveSender(4))
+        ));
+        assert!(matches!(
+            recv_3.try_recv(),
+            Ok(DroneCommand::RemoveSender(4))
+        ));
+
+        running.handle_node_event(&*leaving);
+        assert!(!running.get_network_view().contains(4));
+        assert!(!running.get_clients().contains_key(&4));
+
+        running.drone_command_channels.insert(2, drone_2);
+        running.drone_command_channels.insert(3, drone_3);
+        running.stop_simulation();
+    }
+}
@@ -5,7 +5,7 @@ use wg_internal::network::NodeId;
 use wg_internal::packet::{NodeType, Packet};
 use common::{FragmentAssembler, RoutingHandler};
 use common::packet_processor::Processor;
-use common::types::{ChatCommand, ChatEvent, ChatRequest, ChatResponse, Command, Event, NodeCommand, NodeEvent, ServerType};
+use common::types::{ChatCommand, ChatEvent, ChatRequest, ChatResponse, Command, Event, NodeCommand, NodeEvent, ServerType, PROTOCOL_VERSION};
 
 pub struct ChatServer {
     routing_handler: RoutingHandler,
@@ -54,7 +54,7 @@ impl Processor for ChatServer {
         &mut self.routing_handler
     }
 
-    fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, session_id: u64) {
+    fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, _session_id: u64) {
         let _ = self.controller_send.send(Box::new(NodeEvent::MessageReceived {
             notification_from: self.id,
             from
@@ -66,8 +66,12 @@ impl Processor for ChatServer {
                         notification_from: self.id,
                         from
                     }));
-                    if let Ok(res) = serde_json::to_vec(&ChatResponse::ServerType { server_type: ServerType::ChatServer }) {
-                        let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                    if let Ok(res) = serde_json::to_vec(&ChatResponse::ServerType {
+                        server_type: ServerType::ChatServer,
+                        protocol_version: PROTOCOL_VERSION,
+                        capabilities: vec![],
+                    }) {
+                        let _ = self.routing_handler.send_message(&res, from, None);
                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                             notification_from: self.id,
                             to: from
@@ -77,7 +81,7 @@ impl Processor for ChatServer {
                 ChatRequest::RegistrationToChat { client_id } => {
                     self.registered_clients.insert(client_id);
                     if let Ok(res) = serde_json::to_vec(&ChatResponse::RegistrationSuccess) {
-                        let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                        let _ = self.routing_handler.send_message(&res, from, None);
                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                             notification_from: self.id,
                             to: from
@@ -95,7 +99,7 @@ impl Processor for ChatServer {
                     }));
                     let client_list = self.registered_clients.iter().copied().collect::<Vec<_>>();
                     if let Ok(res) = serde_json::to_vec(&ChatResponse::ClientList {list_of_client_ids: client_list}) {
-                        let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                        let _ = self.routing_handler.send_message(&res, from, None);
                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                             notification_from: self.id,
                             to: from
@@ -107,7 +111,7 @@ impl Processor for ChatServer {
                         if let Ok(res) = serde_json::to_vec(&ChatResponse::ErrorWrongClientId {
                             wrong_id: client_id
                         }) {
-                            let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                            let _ = self.routing_handler.send_message(&res, from, None);
                             let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                                 notification_from: self.id,
                                 to: from
@@ -116,7 +120,7 @@ impl Processor for ChatServer {
                         return
                     }
                     if let Ok(res) = serde_json::to_vec(&ChatResponse::MessageFrom { client_id: from, message }) {
-                        let _ = self.routing_handler.send_message(&res, client_id, Some(session_id));
+                        let _ = self.routing_handler.send_message(&res, client_id, None);
                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                             notification_from: self.id,
                             to: client_id
@@ -133,7 +137,12 @@ impl Processor for ChatServer {
             match cmd {
                 NodeCommand::AddSender(node_id, sender) => self.routing_handler.add_neighbor(*node_id, sender.clone()),
                 NodeCommand::RemoveSender(node_id) => self.routing_handler.remove_neighbor(*node_id),
-                NodeCommand::Shutdown => return true
+                NodeCommand::Shutdown => return true,
+                NodeCommand::GetStats => self.report_stats(),
+                NodeCommand::GetNetworkView => self.report_network_view(),
+                NodeCommand::StartFlood => {
+                    let _ = self.routing_handler.start_flood();
+                }
             }
         } else if let Some(ChatCommand::GetRegisteredClients) = cmd.downcast_ref::<ChatCommand>() {
             let registered_clients = self.get_registered_clients();
@@ -196,6 +205,28 @@ mod communication_server_tests {
         server.handle_msg(serde_json::to_vec(&invalid_message).unwrap(), 10, 104);
     }
 
+    #[test]
+    /// Tests that replies are sent on a session opened by the server, not on the client's one
+    fn test_reply_uses_server_session_id() {
+        use common::routing_handler::SESSION_COUNTER_BITS;
+        use wg_internal::packet::{FloodResponse, PacketType};
+
+        let (mut server, packet_recv, _) = create_test_chat_server();
+        server.routing_handler.handle_flood_response(&FloodResponse {
+            flood_id: 0,
+            path_trace: vec![(1, NodeType::Server), (2, NodeType::Client)],
+        }).unwrap();
+
+        let request = ChatRequest::RegistrationToChat { client_id: 2 };
+        let client_session = (2 << SESSION_COUNTER_BITS) | 7;
+        server.handle_msg(serde_json::to_vec(&request).unwrap(), 2, client_session);
+
+        let reply = packet_recv.try_recv().unwrap();
+        assert!(matches!(reply.pack_type, PacketType::MsgFragment(_)));
+        assert_ne!(reply.session_id, client_session);
+        assert_eq!(reply.session_id >> SESSION_COUNTER_BITS, 1);
+    }
+
     #[test]
     /// Tests malformed message handling, it shouldn't panick
     fn test_malformed_message_handling() {
@@ -4,8 +4,9 @@ use uuid::Uuid;
 use wg_internal::network::NodeId;
 use wg_internal::packet::{NodeType, Packet};
 use common::{FragmentAssembler, RoutingHandler};
+use common::envelope::Codec;
 use common::packet_processor::Processor;
-use common::types::{Command, Event, MediaFile, NodeCommand, NodeEvent, ServerType, WebCommand, WebEvent, WebRequest, WebResponse};
+use common::types::{Capability, Command, Event, MediaFile, NodeCommand, NodeEvent, ServerType, PROTOCOL_VERSION, WebCommand, WebEvent, WebRequest, WebResponse};
 use common::file_conversion;
 
 pub struct MediaServer {
@@ -80,7 +81,7 @@ impl Processor for MediaServer {
         &mut self.routing_handler
     }
 
-    fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, session_id: u64) {
+    fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, _session_id: u64) {
         let _ = self.controller_send.send(Box::new(NodeEvent::MessageReceived {
             notification_from: self.id,
             from
@@ -92,15 +93,20 @@ impl Processor for MediaServer {
                         notification_from: self.id,
                         from
                     }));
-                    if let Ok(res) = serde_json::to_vec(&WebResponse::ServerType { server_type: ServerType::MediaServer }) {
-                        let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                    if let Ok(res) = serde_json::to_vec(&WebResponse::ServerType {
+                        server_type: ServerType::MediaServer,
+                        protocol_version: PROTOCOL_VERSION,
+                        capabilities: vec![Capability::Compression],
+                    }) {
+                        let _ = self.routing_handler.send_message(&res, from, None);
                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                             notification_from: self.id,
                             to: from
                         }));
                     }
                 }
-                WebRequest::MediaQuery { media_id } => {
+                WebRequest::MediaQuery { media_id, accepted_codecs } => {
+                    let codec = Codec::negotiate(&accepted_codecs);
                     let _ = self.controller_send.send(Box::new(WebEvent::FileRequested {
                         notification_from: self.id,
                         from,
@@ -109,11 +115,11 @@ impl Processor for MediaServer {
                     match Uuid::parse_str(&media_id) {
                         Ok(uuid) => {
                             if let Some(media_file) = self.get_media_by_id(uuid) {
-                                if let Ok(serialized_media) = serde_json::to_vec(media_file)
-                                    && let Ok(res) = serde_json::to_vec(&WebResponse::MediaFile {
+                                if let Ok(serialized_media) = codec.nested().encode(media_file)
+                                    && let Ok(res) = (WebResponse::MediaFile {
                                         media_data: serialized_media
-                                    }) {
-                                        let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                                    }).encode(codec) {
+                                        let _ = self.routing_handler.send_message_with_codec(&res, codec, from, None);
                                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                                             notification_from: self.id,
                                             to: from
@@ -124,7 +130,7 @@ impl Processor for MediaServer {
                                         }));
                                 }
                             } else if let Ok(res) = serde_json::to_vec(&WebResponse::ErrorFileNotFound(uuid)) {
-                                    let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                                    let _ = self.routing_handler.send_message(&res, from, None);
                                     let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                                         notification_from: self.id,
                                         to: from
@@ -133,7 +139,7 @@ impl Processor for MediaServer {
                         }
                         Err(_) => {
                             if let Ok(res) = serde_json::to_vec(&WebResponse::BadUuid(media_id.clone())) {
-                                let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                                let _ = self.routing_handler.send_message(&res, from, None);
                                 let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                                     notification_from: self.id,
                                     to: from
@@ -158,7 +164,12 @@ impl Processor for MediaServer {
             match cmd {
                 NodeCommand::AddSender(node_id, sender) => self.routing_handler.add_neighbor(*node_id, sender.clone()),
                 NodeCommand::RemoveSender(node_id) => self.routing_handler.remove_neighbor(*node_id),
-                NodeCommand::Shutdown => return true
+                NodeCommand::Shutdown => return true,
+                NodeCommand::GetStats => self.report_stats(),
+                NodeCommand::GetNetworkView => self.report_network_view(),
+                NodeCommand::StartFlood => {
+                    let _ = self.routing_handler.start_flood();
+                }
             }
         }  else if let Some(cmd) = cmd.downcast_ref::<WebCommand>() {
             match cmd {
@@ -324,4 +335,47 @@ mod media_server_tests {
         assert_eq!(media.get_size(), 6);
         assert_eq!(media.get_content().len(), 2);
     }
+
+    #[test]
+    fn test_media_query_negotiates_codec() {
+        let (_controller_send, controller_recv) = unbounded();
+        let (event_send, _event_recv) = unbounded::<Box<dyn Event>>();
+        let (_, packet_recv) = unbounded();
+        let (neighbor_send, neighbor_recv) = unbounded();
+
+        let mut server = MediaServer::new(1, HashMap::new(), packet_recv, controller_recv, event_send);
+        server.routing_handler.add_neighbor(2, neighbor_send);
+        // no acks come back, let the whole response out at once
+        server.routing_handler.set_window_policy(256, 256);
+        let media = MediaFile::from_u8("test.bin".to_string(), &[7u8; 2000]);
+        let media_id = media.id;
+        server.add_media_file(media.clone());
+
+        for (session_id, accepted_codecs, expected) in [
+            (100, vec![], Codec::Json),
+            (101, Codec::supported_ids(), Codec::BincodeDeflate),
+        ] {
+            let request = WebRequest::MediaQuery { media_id: media_id.to_string(), accepted_codecs };
+            server.handle_msg(serde_json::to_vec(&request).unwrap(), 2, session_id);
+
+            let mut assembler = FragmentAssembler::default();
+            let mut message = None;
+            for packet in neighbor_recv.try_iter() {
+                if let wg_internal::packet::PacketType::MsgFragment(fragment) = packet.pack_type {
+                    message = assembler.add_fragment(fragment, packet.session_id, 1);
+                }
+            }
+            let envelope = common::envelope::Envelope::from_bytes(&message.unwrap()).unwrap();
+            assert_eq!(envelope.codec, expected);
+            let Ok(WebResponse::MediaFile { media_data }) = WebResponse::decode(&envelope.payload, expected) else {
+                panic!("expected a media file");
+            };
+            assert_eq!(expected.nested().decode::<MediaFile>(&media_data).unwrap(), media);
+            if expected != Codec::Json {
+                // the file is compressed once, along with the response: only its framing is added
+                let single = expected.encode(&media).unwrap();
+                assert!(envelope.payload.len() <= single.len() + 8);
+            }
+        }
+    }
 }
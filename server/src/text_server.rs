@@ -5,8 +5,9 @@ use wg_internal::network::NodeId;
 use wg_internal::packet::{NodeType, Packet};
 use common::{FragmentAssembler, RoutingHandler};
 use common::packet_processor::Processor;
-use common::types::{Command, Event, NodeCommand, NodeEvent, ServerType, TextFile, WebCommand, WebEvent, WebRequest, WebResponse};
+use common::types::{Capability, Command, Event, NodeCommand, NodeEvent, ServerType, PROTOCOL_VERSION, TextFile, WebCommand, WebEvent, WebRequest, WebResponse};
 use common::file_conversion;
+use common::envelope::Codec;
 
 pub struct TextServer {
     routing_handler: RoutingHandler,
@@ -80,7 +81,7 @@ impl Processor for TextServer {
         &mut self.routing_handler
     }
 
-    fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, session_id: u64) {
+    fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, _session_id: u64) {
         let _ = self.controller_send.send(Box::new(NodeEvent::MessageReceived {
             notification_from: self.id,
             from
@@ -92,8 +93,12 @@ impl Processor for TextServer {
                         notification_from: self.id,
                         from
                     }));
-                    if let Ok(res) = serde_json::to_vec(&WebResponse::ServerType { server_type: ServerType::TextServer }) {
-                        let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                    if let Ok(res) = serde_json::to_vec(&WebResponse::ServerType {
+                        server_type: ServerType::TextServer,
+                        protocol_version: PROTOCOL_VERSION,
+                        capabilities: vec![Capability::Compression],
+                    }) {
+                        let _ = self.routing_handler.send_message(&res, from, None);
                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                             notification_from: self.id,
                             to: from
@@ -107,14 +112,15 @@ impl Processor for TextServer {
                     }));
                     let files_list = self.get_files_list();
                     if let Ok(res) = serde_json::to_vec(&WebResponse::TextFilesList {files: files_list}) {
-                        let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                        let _ = self.routing_handler.send_message(&res, from, None);
                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                             notification_from: self.id,
                             to: from
                         }));
                     }
                 }
-                WebRequest::FileQuery { file_id } => {
+                WebRequest::FileQuery { file_id, accepted_codecs } => {
+                    let codec = Codec::negotiate(&accepted_codecs);
                     let _ = self.controller_send.send(Box::new(WebEvent::FileRequested {
                         notification_from: self.id,
                         from,
@@ -123,9 +129,9 @@ impl Processor for TextServer {
                     match Uuid::parse_str(&file_id) {
                         Ok(uuid) => {
                             if let Some(text_file) = self.get_file_by_id(uuid)
-                                && let Ok(serialized_file) = serde_json::to_vec(text_file) {
-                                    if let Ok(res) = serde_json::to_vec(&WebResponse::TextFile { file_data: serialized_file }) {
-                                        let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                                && let Ok(serialized_file) = codec.nested().encode(text_file) {
+                                    if let Ok(res) = (WebResponse::TextFile { file_data: serialized_file }).encode(codec) {
+                                        let _ = self.routing_handler.send_message_with_codec(&res, codec, from, None);
                                         let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                                             notification_from: self.id,
                                             to: from
@@ -136,7 +142,7 @@ impl Processor for TextServer {
                                         }));
                                     }
                             } else if let Ok(res) = serde_json::to_vec(&WebResponse::ErrorFileNotFound(uuid)) {
-                                let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                                let _ = self.routing_handler.send_message(&res, from, None);
                                 let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                                     notification_from: self.id,
                                     to: from
@@ -145,7 +151,7 @@ impl Processor for TextServer {
                         }
                         Err(_) => {
                             if let Ok(res) = serde_json::to_vec(&WebResponse::BadUuid(file_id.clone())) {
-                                let _ = self.routing_handler.send_message(&res, from, Some(session_id));
+                                let _ = self.routing_handler.send_message(&res, from, None);
                                 let _ = self.controller_send.send(Box::new(NodeEvent::MessageSent {
                                     notification_from: self.id,
                                     to: from
@@ -170,7 +176,12 @@ impl Processor for TextServer {
             match cmd {
                 NodeCommand::AddSender(node_id, sender) => self.routing_handler.add_neighbor(*node_id, sender.clone()),
                 NodeCommand::RemoveSender(node_id) => self.routing_handler.remove_neighbor(*node_id),
-                NodeCommand::Shutdown => return true
+                NodeCommand::Shutdown => return true,
+                NodeCommand::GetStats => self.report_stats(),
+                NodeCommand::GetNetworkView => self.report_network_view(),
+                NodeCommand::StartFlood => {
+                    let _ = self.routing_handler.start_flood();
+                }
             }
         }  else if let Some(cmd) = cmd.downcast_ref::<WebCommand>() {
             match cmd {
@@ -357,18 +368,21 @@ mod text_server_tests {
         server.handle_msg(serialized, 2, 101);
 
         let file_request = WebRequest::FileQuery {
-            file_id: file_id.to_string()
+            file_id: file_id.to_string(),
+            accepted_codecs: vec![],
         };
         let serialized = serde_json::to_vec(&file_request).unwrap();
         server.handle_msg(serialized, 2, 102);
 
         let invalid_request = WebRequest::FileQuery {
-            file_id: "invalid-uuid".to_string()
+            file_id: "invalid-uuid".to_string(),
+            accepted_codecs: vec![],
         };
         let _serialized = serde_json::to_vec(&invalid_request).unwrap();
 
         let nonexistent_request = WebRequest::FileQuery {
-            file_id: Uuid::new_v4().to_string()
+            file_id: Uuid::new_v4().to_string(),
+            accepted_codecs: vec![],
         };
         let serialized = serde_json::to_vec(&nonexistent_request).unwrap();
         server.handle_msg(serialized, 2, 103);